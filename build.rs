@@ -0,0 +1,18 @@
+//! Records the git revision of the source tree in `GIT_REVISION` for the run manifest.
+
+use std::process::Command;
+
+fn main() {
+    for path in [".git/HEAD", ".git/index", ".git/refs", "src"] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    let revision = Command::new("git")
+        .args(["describe", "--always", "--dirty", "--abbrev=40"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(revision) = revision {
+        println!("cargo:rustc-env=GIT_REVISION={}", revision.trim());
+    }
+}
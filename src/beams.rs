@@ -0,0 +1,122 @@
+//! The gaussian beams forming the crossed dipole trap.
+
+use lib::dipole::DipoleLight;
+use lib::integrator::{Step, Timestep};
+use lib::laser::frame::Frame;
+use lib::laser::gaussian::{calculate_rayleigh_range, GaussianBeam};
+use nalgebra::Vector3;
+use specs::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::config::{BeamConfig, ExperimentConfig, RampMode};
+use crate::ramp::AnalyticBeamRamp;
+
+/// Position of a beam in the `beams` list of the experiment file.
+pub struct BeamIndex(pub usize);
+
+impl Component for BeamIndex {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// The [GaussianBeam] described by `beam` at the start of the simulation.
+pub fn gaussian_beam(beam: &BeamConfig) -> GaussianBeam {
+    let e_radius = beam.waist / 2.0_f64.sqrt();
+    GaussianBeam {
+        intersection: Vector3::from(beam.intersection),
+        e_radius,
+        power: beam.power,
+        direction: Vector3::from(beam.direction).normalize(),
+        rayleigh_range: calculate_rayleigh_range(&beam.wavelength, &e_radius),
+        ellipticity: beam.ellipticity,
+    }
+}
+
+/// Returns two unit vectors perpendicular to the beam `direction`, used as the beam's transverse frame.
+pub fn beam_frame(direction: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let reference = if direction.z.abs() < 0.9 { Vector3::z() } else { Vector3::x() };
+    let x_vector = reference.cross(direction).normalize();
+    let y_vector = direction.cross(&x_vector).normalize();
+    (x_vector, y_vector)
+}
+
+/// Creates an entity for each beam of the experiment, with its ramp if it has one.
+pub fn create_beams(world: &mut World, config: &ExperimentConfig) {
+    let dt = config.simulation.timestep;
+    let end_time = config.simulation.steps as f64 * dt;
+    let resolution = config.ramp.resolution.unwrap_or(dt);
+
+    for (index, beam) in config.beams.iter().enumerate() {
+        let gaussian_beam = gaussian_beam(beam);
+        let (x_vector, y_vector) = beam_frame(&gaussian_beam.direction);
+
+        let mut entity = world
+            .create_entity()
+            .with(gaussian_beam)
+            .with(DipoleLight {
+                wavelength: beam.wavelength,
+            })
+            .with(Frame { x_vector, y_vector })
+            .with(BeamIndex(index));
+        if let Some(ramp) = &beam.ramp {
+            entity = match config.ramp.mode {
+                RampMode::Analytic => entity.with(AnalyticBeamRamp {
+                    initial: gaussian_beam,
+                    ramp: ramp.clone(),
+                }),
+                RampMode::Keyframes => entity.with(ramp.keyframes(
+                    &gaussian_beam,
+                    beam.wavelength,
+                    resolution,
+                    end_time,
+                )),
+            };
+        }
+        entity.build();
+    }
+}
+
+/// Writes the power of every beam to a csv file every `interval` steps.
+pub struct BeamPowerOutputSystem {
+    writer: BufWriter<File>,
+    interval: u64,
+}
+
+impl BeamPowerOutputSystem {
+    pub fn new(path: &Path, beam_number: usize, interval: u64) -> Result<Self, Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let columns: Vec<String> = (0..beam_number).map(|i| format!("beam_{}_power", i)).collect();
+        writeln!(writer, "step,time,{}", columns.join(","))?;
+        Ok(BeamPowerOutputSystem { writer, interval })
+    }
+}
+
+impl<'a> System<'a> for BeamPowerOutputSystem {
+    type SystemData = (
+        ReadStorage<'a, GaussianBeam>,
+        ReadStorage<'a, BeamIndex>,
+        ReadExpect<'a, Timestep>,
+        ReadExpect<'a, Step>,
+    );
+
+    fn run(&mut self, (beams, indices, timestep, step): Self::SystemData) {
+        if step.n % self.interval != 0 {
+            return;
+        }
+        let mut powers: Vec<(usize, f64)> = (&beams, &indices)
+            .join()
+            .map(|(beam, index)| (index.0, beam.power))
+            .collect();
+        powers.sort_by_key(|(index, _)| *index);
+        let powers: Vec<String> = powers.iter().map(|(_, power)| power.to_string()).collect();
+        writeln!(
+            self.writer,
+            "{},{},{}",
+            step.n,
+            step.n as f64 * timestep.delta,
+            powers.join(",")
+        )
+        .expect("Could not write beam power file.");
+    }
+}
@@ -0,0 +1,177 @@
+//! Snapshots of a running simulation, from which it can be resumed.
+//!
+//! A checkpoint holds everything that is not rebuilt from the experiment file: the position,
+//! velocity, force and mass of every atom, the step reached, the progress of keyframed beam ramps,
+//! the collision, evaporation and three-body loss counts and the position of the run's random
+//! generator. The force is kept because the velocity Verlet integrator uses the force of the
+//! previous step.
+//!
+//! Checkpoints are written as `checkpoint_<step>.json` into the run directory, every
+//! `output.checkpoint_interval` steps. Floats are written so that they read back exactly, so a
+//! resumed run follows the interrupted one bit for bit, except that atomecs draws collisions from
+//! an unseeded generator. Collisions of the `s_wave` model are drawn from the run's seed, and the
+//! position of their generator is kept too.
+
+use lib::atom::{Atom, Force, Mass, Position, Velocity};
+use lib::collisions::CollisionsTracker;
+use lib::destructor::ToBeDestroyed;
+use lib::integrator::Step;
+use lib::laser::gaussian::GaussianBeam;
+use lib::ramp::Ramp;
+use nalgebra::Vector3;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::beams::BeamIndex;
+use crate::collisions::CollisionRng;
+use crate::config::ExperimentConfig;
+use crate::evaporation::EvaporationTracker;
+use crate::output::{is_plain_name, RunDirectory};
+use crate::seed::RunSeed;
+use crate::three_body::ThreeBodyTracker;
+
+/// State of a single atom.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct AtomState {
+    pub pos: Vector3<f64>,
+    pub vel: Vector3<f64>,
+    /// Force at the end of the last step, in N.
+    pub force: Vector3<f64>,
+    /// In amu.
+    pub mass: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Name of the run the checkpoint was taken from.
+    pub run_name: String,
+    pub seed: RunSeed,
+    /// Number of steps completed.
+    pub step: u64,
+    /// Word position of the run's random generator, see `ChaCha8Rng::get_word_pos`.
+    pub rng_word_pos: u128,
+    /// The resolved experiment configuration of the run.
+    pub config: ExperimentConfig,
+    pub atoms: Vec<AtomState>,
+    /// `Ramp::prev` of each keyframed beam, by beam index.
+    pub ramp_progress: Vec<(usize, usize)>,
+    pub num_collisions: Vec<i32>,
+    pub num_atoms: Vec<f64>,
+    pub num_particles: Vec<i32>,
+    /// Atoms removed by the energy cut so far.
+    #[serde(default)]
+    pub evaporated: u64,
+    /// Particles lost to three-body recombination so far.
+    #[serde(default)]
+    pub three_body_lost: u64,
+    /// Word position of the collision generator, for the `s_wave` collision model.
+    #[serde(default)]
+    pub collision_rng_word_pos: Option<u128>,
+}
+
+impl Checkpoint {
+    /// Captures the state of `world` at the end of the current step. Atoms marked [ToBeDestroyed]
+    /// in the step are already counted as lost, so they are left out.
+    pub fn capture(world: &World, config: &ExperimentConfig, run_name: &str, seed: RunSeed, rng: &ChaCha8Rng) -> Self {
+        let atoms = (
+            &world.read_storage::<Atom>(),
+            &world.read_storage::<Position>(),
+            &world.read_storage::<Velocity>(),
+            &world.read_storage::<Force>(),
+            &world.read_storage::<Mass>(),
+            !&world.read_storage::<ToBeDestroyed>(),
+        )
+            .join()
+            .map(|(_, pos, vel, force, mass, _)| AtomState {
+                pos: pos.pos,
+                vel: vel.vel,
+                force: force.force,
+                mass: mass.value,
+            })
+            .collect();
+        let mut ramp_progress: Vec<(usize, usize)> = (
+            &world.read_storage::<BeamIndex>(),
+            &world.read_storage::<Ramp<GaussianBeam>>(),
+        )
+            .join()
+            .map(|(index, ramp)| (index.0, ramp.prev))
+            .collect();
+        ramp_progress.sort_unstable();
+        let tracker = world.read_resource::<CollisionsTracker>();
+        let evaporated = world
+            .try_fetch::<EvaporationTracker>()
+            .map_or(0, |evaporation| evaporation.total_lost);
+        let three_body_lost = world
+            .try_fetch::<ThreeBodyTracker>()
+            .map_or(0, |three_body| three_body.total_lost);
+        let collision_rng_word_pos = world.try_fetch::<CollisionRng>().map(|rng| rng.0.get_word_pos());
+
+        Checkpoint {
+            run_name: run_name.to_string(),
+            seed,
+            step: world.read_resource::<Step>().n,
+            rng_word_pos: rng.get_word_pos(),
+            config: config.clone(),
+            atoms,
+            ramp_progress,
+            num_collisions: tracker.num_collisions.clone(),
+            num_atoms: tracker.num_atoms.clone(),
+            num_particles: tracker.num_particles.clone(),
+            evaporated,
+            three_body_lost,
+            collision_rng_word_pos,
+        }
+    }
+
+    /// Restores the step, beam ramps, collision, evaporation and three-body trackers and the
+    /// collision generator into `world`, and the position of `rng`. The atoms are created by the caller.
+    pub fn restore(&self, world: &mut World, rng: &mut ChaCha8Rng) {
+        world.insert(Step { n: self.step });
+        world.insert(CollisionsTracker {
+            num_collisions: self.num_collisions.clone(),
+            num_atoms: self.num_atoms.clone(),
+            num_particles: self.num_particles.clone(),
+        });
+        if let Some(mut evaporation) = world.try_fetch_mut::<EvaporationTracker>() {
+            evaporation.total_lost = self.evaporated;
+        }
+        if let Some(mut three_body) = world.try_fetch_mut::<ThreeBodyTracker>() {
+            three_body.total_lost = self.three_body_lost;
+        }
+        if let (Some(mut collision_rng), Some(word_pos)) =
+            (world.try_fetch_mut::<CollisionRng>(), self.collision_rng_word_pos)
+        {
+            collision_rng.0.set_word_pos(word_pos);
+        }
+        let mut ramps = world.write_storage::<Ramp<GaussianBeam>>();
+        for (index, ramp) in (&world.read_storage::<BeamIndex>(), &mut ramps).join() {
+            if let Some((_, prev)) = self.ramp_progress.iter().find(|(i, _)| *i == index.0) {
+                ramp.prev = *prev;
+            }
+        }
+        rng.set_word_pos(self.rng_word_pos);
+    }
+
+    /// Reads a checkpoint, checking that its run name can be used to name the directory of the
+    /// resumed run.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let checkpoint: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if !is_plain_name(&checkpoint.run_name) {
+            return Err(Error::new(ErrorKind::InvalidData, "run_name must be a plain directory name"));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Writes the checkpoint into `run_dir`, returning its path.
+    pub fn write(&self, run_dir: &RunDirectory) -> Result<PathBuf, Error> {
+        let path = run_dir.file(&format!("checkpoint_{}.json", self.step));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,410 @@
+//! Initial phase-space distribution of the atom cloud.
+
+use lib::constant::{BOLTZCONST, PI};
+use nalgebra::{Matrix3, Matrix6, Vector3, Vector6};
+use rand::seq::index;
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal, UnitSphere};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::config::{invalid, non_negative, positive, ConfigError};
+use crate::import;
+use crate::trap::{to_microkelvin, TrapProperties};
+
+/// Steps discarded at the start of the Metropolis chain of [CloudDistribution::Boltzmann].
+const BURN_IN: u64 = 5_000;
+/// Steps of the Metropolis chain between consecutive samples, to reduce their correlation.
+const THINNING: u64 = 20;
+/// Bisection steps inverting the distribution of kinetic energies of [CloudDistribution::Boltzmann].
+const ENERGY_BISECTIONS: usize = 60;
+
+/// Position and velocity of a single atom.
+#[derive(Clone, Copy, Debug)]
+pub struct PhaseSpacePoint {
+    pub pos: Vector3<f64>,
+    pub vel: Vector3<f64>,
+}
+
+/// How the initial positions and velocities are sampled.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CloudDistribution {
+    /// Uncorrelated gaussians centred on the origin, with the given variances along x, y, z.
+    Gaussian {
+        /// In m^2.
+        position_variance: [f64; 3],
+        /// In (m/s)^2.
+        velocity_variance: [f64; 3],
+    },
+    /// A thermal cloud at `temperature` (in K) in the harmonic approximation of the trap.
+    ///
+    /// The trap frequencies are calculated from the beams, unless `frequencies` gives them
+    /// explicitly along x, y, z (in Hz). The cloud is displaced by the gravitational sag.
+    Thermal {
+        temperature: f64,
+        frequencies: Option<[f64; 3]>,
+    },
+    /// A cloud in thermal equilibrium at `temperature` (in K) in the full dipole potential of
+    /// the beams, truncated at the trap depth: `f(r, v) ~ exp(-E / kT)` for total energies `E`
+    /// below the escape energy. Unlike `thermal`, this holds for hot clouds that explore the
+    /// anharmonic region of the trap.
+    Boltzmann { temperature: f64 },
+    /// A gaussian with full phase-space mean and covariance over `(x, y, z, vx, vy, vz)`, in SI
+    /// units, so correlations between axes and between positions and velocities can be expressed,
+    /// eg for a transported, rotated or breathing cloud.
+    PhaseSpace {
+        #[serde(default)]
+        mean: [f64; 6],
+        covariance: Box<[[f64; 6]; 6]>,
+    },
+    /// Atoms read from a csv file with columns `x, y, z, vx, vy, vz`, in SI units, eg measured
+    /// positions and velocities or the output of another simulation. A header line is allowed.
+    Csv { path: PathBuf },
+    /// Atoms read from the `pos.txt` and `vel.txt` files of a previous run, as written by
+    /// `FileOutputPlugin` in its text format, to continue from one of its frames. The frame at
+    /// `step` is used, or the last frame if it is omitted.
+    Frame {
+        positions: PathBuf,
+        velocities: PathBuf,
+        step: Option<u64>,
+    },
+}
+
+impl CloudDistribution {
+    /// Draws `number` atoms held in `trap`.
+    ///
+    /// Atoms read from a file are used as they are, or a random subset of them if the file holds
+    /// more than `number`; it is an error for it to hold fewer.
+    pub fn sample<R: Rng>(
+        &self,
+        number: u64,
+        trap: &TrapProperties,
+        rng: &mut R,
+    ) -> Result<Vec<PhaseSpacePoint>, ConfigError> {
+        let mass = trap.mass;
+        let points = match self {
+            CloudDistribution::Gaussian {
+                position_variance,
+                velocity_variance,
+            } => sample_principal_axes(
+                number,
+                &Matrix3::identity(),
+                &Vector3::from(*position_variance).map(f64::sqrt),
+                &Vector3::from(*velocity_variance).map(f64::sqrt),
+                rng,
+            ),
+            CloudDistribution::Thermal {
+                temperature,
+                frequencies,
+            } => {
+                let (axes, omega) = match frequencies {
+                    Some(f) => (Matrix3::identity(), Vector3::from(*f) * 2.0 * PI),
+                    None => {
+                        let eigen = trap.curvature.symmetric_eigen();
+                        if trap.depth <= 0.0 || eigen.eigenvalues.min() <= 0.0 {
+                            return Err(untrapped());
+                        }
+                        (eigen.eigenvectors, eigen.eigenvalues.map(|k| (k / mass).sqrt()))
+                    }
+                };
+                let velocity_sigma = (BOLTZCONST * temperature / mass).sqrt();
+                let sag = trap.sag();
+                let mut points = sample_principal_axes(
+                    number,
+                    &axes,
+                    &omega.map(|w| velocity_sigma / w),
+                    &Vector3::repeat(velocity_sigma),
+                    rng,
+                );
+                for point in points.iter_mut() {
+                    point.pos += sag;
+                }
+                points
+            }
+            CloudDistribution::Boltzmann { temperature } => {
+                sample_truncated_boltzmann(number, *temperature, trap, rng)?
+            }
+            CloudDistribution::PhaseSpace { mean, covariance } => {
+                sample_phase_space(number, mean, covariance, rng)
+            }
+            CloudDistribution::Csv { path } => choose(number, import::read_csv(path)?, path, rng)?,
+            CloudDistribution::Frame {
+                positions,
+                velocities,
+                step,
+            } => choose(number, import::read_frame(positions, velocities, *step)?, positions, rng)?,
+        };
+        Ok(points)
+    }
+
+    /// Compares the distribution with a thermal cloud in the harmonic approximation of `trap`.
+    pub fn print_check(&self, trap: &TrapProperties) {
+        match self {
+            CloudDistribution::Gaussian {
+                position_variance,
+                velocity_variance,
+            } => {
+                let axes = [Vector3::x(), Vector3::y(), Vector3::z()];
+                for (i, (axis, name)) in axes.iter().zip(["x", "y", "z"]).enumerate() {
+                    let temperature = trap.mass * velocity_variance[i] / BOLTZCONST;
+                    let harmonic_variance = velocity_variance[i] / trap.frequency_along(axis).powi(2);
+                    println!(
+                        "  {}: T = {:.2} uK, position variance {:.3e} m^2, harmonic trap at this T {:.3e} m^2",
+                        name,
+                        temperature * 1e6,
+                        position_variance[i],
+                        harmonic_variance
+                    );
+                }
+            }
+            CloudDistribution::Thermal { temperature, .. }
+            | CloudDistribution::Boltzmann { temperature } => {
+                println!(
+                    "  thermal cloud at {:.2} uK, eta = U / kT = {:.1}",
+                    temperature * 1e6,
+                    to_microkelvin(trap.depth) / (temperature * 1e6)
+                );
+            }
+            CloudDistribution::PhaseSpace { covariance, .. } => {
+                for (i, name) in ["x", "y", "z"].iter().enumerate() {
+                    println!(
+                        "  {}: T = {:.2} uK, position variance {:.3e} m^2",
+                        name,
+                        trap.mass * covariance[i + 3][i + 3] / BOLTZCONST * 1e6,
+                        covariance[i][i]
+                    );
+                }
+            }
+            CloudDistribution::Csv { path } => {
+                println!("  atoms read from {}", path.display());
+            }
+            CloudDistribution::Frame { positions, step, .. } => match step {
+                Some(step) => println!("  atoms read from step {} of {}", step, positions.display()),
+                None => println!("  atoms read from the last frame of {}", positions.display()),
+            },
+        }
+    }
+
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        match self {
+            CloudDistribution::Gaussian {
+                position_variance,
+                velocity_variance,
+            } => {
+                for (axis, v) in ["x", "y", "z"].iter().zip(position_variance.iter()) {
+                    non_negative(format!("{}.position_variance.{}", key, axis), *v)?;
+                }
+                for (axis, v) in ["x", "y", "z"].iter().zip(velocity_variance.iter()) {
+                    non_negative(format!("{}.velocity_variance.{}", key, axis), *v)?;
+                }
+            }
+            CloudDistribution::Thermal {
+                temperature,
+                frequencies,
+            } => {
+                positive(format!("{}.temperature", key), *temperature)?;
+                if let Some(frequencies) = frequencies {
+                    for (axis, f) in ["x", "y", "z"].iter().zip(frequencies.iter()) {
+                        positive(format!("{}.frequencies.{}", key, axis), *f)?;
+                    }
+                }
+            }
+            CloudDistribution::Boltzmann { temperature } => {
+                positive(format!("{}.temperature", key), *temperature)?;
+            }
+            CloudDistribution::PhaseSpace { covariance, .. } => {
+                let covariance = Matrix6::from_fn(|i, j| covariance[i][j]);
+                let scale = covariance.diagonal().amax();
+                if (covariance - covariance.transpose()).amax() > 1e-9 * scale {
+                    return Err(invalid(format!("{}.covariance", key), "must be symmetric"));
+                }
+                if covariance.symmetric_eigenvalues().min() < -1e-9 * scale {
+                    return Err(invalid(format!("{}.covariance", key), "must be positive semi-definite"));
+                }
+            }
+            CloudDistribution::Csv { .. } | CloudDistribution::Frame { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+/// Samples gaussians centred on the origin, uncorrelated along the columns of `axes`, with
+/// standard deviations `position_sigma` and `velocity_sigma` along each.
+fn sample_principal_axes<R: Rng>(
+    number: u64,
+    axes: &Matrix3<f64>,
+    position_sigma: &Vector3<f64>,
+    velocity_sigma: &Vector3<f64>,
+    rng: &mut R,
+) -> Vec<PhaseSpacePoint> {
+    (0..number)
+        .map(|_| PhaseSpacePoint {
+            pos: axes * normal_vector(rng).component_mul(position_sigma),
+            vel: axes * normal_vector(rng).component_mul(velocity_sigma),
+        })
+        .collect()
+}
+
+/// Keeps `number` of the `points` read from `path`, chosen at random if there are more.
+fn choose<R: Rng>(
+    number: u64,
+    mut points: Vec<PhaseSpacePoint>,
+    path: &Path,
+    rng: &mut R,
+) -> Result<Vec<PhaseSpacePoint>, ConfigError> {
+    let number = number as usize;
+    if points.len() < number {
+        return Err(invalid(
+            path.display().to_string(),
+            &format!("holds {} atoms, fewer than atoms.number = {}", points.len(), number),
+        ));
+    }
+    if points.len() > number {
+        points = index::sample(rng, points.len(), number)
+            .into_iter()
+            .map(|i| points[i])
+            .collect();
+    }
+    Ok(points)
+}
+
+/// Samples a gaussian with the given phase-space `mean` and `covariance`.
+///
+/// The covariance is factorised as `V sqrt(L)` from its eigendecomposition rather than by
+/// Cholesky, so that degenerate (eg zero width) directions are allowed.
+fn sample_phase_space<R: Rng>(
+    number: u64,
+    mean: &[f64; 6],
+    covariance: &[[f64; 6]; 6],
+    rng: &mut R,
+) -> Vec<PhaseSpacePoint> {
+    let mean = Vector6::from_column_slice(mean);
+    let eigen = Matrix6::from_fn(|i, j| covariance[i][j]).symmetric_eigen();
+    let factor = eigen.eigenvectors * Matrix6::from_diagonal(&eigen.eigenvalues.map(|l| l.max(0.0).sqrt()));
+
+    (0..number)
+        .map(|_| {
+            let z = Vector6::from_fn(|_, _| rng.sample(StandardNormal));
+            let x = mean + factor * z;
+            PhaseSpacePoint {
+                pos: x.fixed_rows::<3>(0).into_owned(),
+                vel: x.fixed_rows::<3>(3).into_owned(),
+            }
+        })
+        .collect()
+}
+
+/// Samples the truncated Boltzmann distribution in the potential of `trap`.
+///
+/// Positions are drawn with a Metropolis random walk from the marginal density
+/// `exp(-U / kT) P(3/2, (E_t - U) / kT)`, where `E_t` is the escape energy and the regularised
+/// incomplete gamma function `P` is the fraction of Maxwell-Boltzmann velocities that keep the
+/// atom below it. The kinetic energy `E` is then drawn from the Maxwell-Boltzmann distribution
+/// truncated at `E_t - U`, by inverting its cumulative distribution `P(3/2, E / kT)`, and the
+/// velocity is given an isotropic direction.
+fn sample_truncated_boltzmann<R: Rng>(
+    number: u64,
+    temperature: f64,
+    trap: &TrapProperties,
+    rng: &mut R,
+) -> Result<Vec<PhaseSpacePoint>, ConfigError> {
+    if trap.depth <= 0.0 {
+        return Err(untrapped());
+    }
+    let kt = BOLTZCONST * temperature;
+    let escape_energy = trap.escape_energy;
+    let log_density = |pos: &Vector3<f64>| -> f64 {
+        let u = trap.potential(pos);
+        if u >= escape_energy {
+            return f64::NEG_INFINITY;
+        }
+        -u / kt + lower_gamma_three_halves((escape_energy - u) / kt).ln()
+    };
+
+    // Random walk steps matched to the thermal widths of the harmonic trap.
+    let eigen = trap.curvature.symmetric_eigen();
+    let step = eigen.eigenvalues.map(|k| (kt / k.max(f64::MIN_POSITIVE)).sqrt());
+
+    let mut pos = trap.minimum;
+    let mut log_p = log_density(&pos);
+    if !log_p.is_finite() {
+        return Err(untrapped());
+    }
+    let mut points = Vec::with_capacity(number as usize);
+    let mut i: u64 = 0;
+    while points.len() < number as usize {
+        let proposal = pos + eigen.eigenvectors * normal_vector(rng).component_mul(&step);
+        let log_p_proposal = log_density(&proposal);
+        if log_p_proposal - log_p >= rng.gen::<f64>().ln() {
+            pos = proposal;
+            log_p = log_p_proposal;
+        }
+
+        i += 1;
+        if i <= BURN_IN || i % THINNING != BURN_IN % THINNING {
+            continue;
+        }
+        let kinetic = truncated_kinetic_energy((escape_energy - trap.potential(&pos)) / kt, rng) * kt;
+        let direction = Vector3::from(UnitSphere.sample(rng));
+        points.push(PhaseSpacePoint {
+            pos,
+            vel: direction * (2.0 * kinetic / trap.mass).sqrt(),
+        });
+    }
+    Ok(points)
+}
+
+/// The error for a thermal distribution in a trap that cannot hold atoms.
+fn untrapped() -> ConfigError {
+    invalid(
+        "atoms.distribution",
+        "needs a trap that holds atoms, but the initial trap has no depth",
+    )
+}
+
+/// Draws a kinetic energy, in units of `kT`, from the Maxwell-Boltzmann distribution truncated at
+/// `max`, by bisecting the cumulative distribution `P(3/2, x)` for a uniform fraction of `P(3/2, max)`.
+fn truncated_kinetic_energy<R: Rng>(max: f64, rng: &mut R) -> f64 {
+    let target = rng.gen::<f64>() * lower_gamma_three_halves(max);
+    let (mut low, mut high) = (0.0, max);
+    for _ in 0..ENERGY_BISECTIONS {
+        let mid = 0.5 * (low + high);
+        if lower_gamma_three_halves(mid) < target {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    0.5 * (low + high)
+}
+
+/// Regularised lower incomplete gamma function `P(3/2, x) = erf(sqrt(x)) - 2 sqrt(x / pi) exp(-x)`.
+fn lower_gamma_three_halves(x: f64) -> f64 {
+    if x < 0.05 {
+        // Leading terms of the series, avoiding the cancellation in the closed form.
+        4.0 / (3.0 * PI.sqrt()) * x.powf(1.5) * (1.0 - 0.6 * x)
+    } else {
+        erf(x.sqrt()) - 2.0 * (x / PI).sqrt() * (-x).exp()
+    }
+}
+
+/// Error function, Abramowitz and Stegun 7.1.26, accurate to 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - polynomial * (-x * x).exp();
+    if x >= 0.0 {
+        y
+    } else {
+        -y
+    }
+}
+
+fn normal_vector<R: Rng>(rng: &mut R) -> Vector3<f64> {
+    Vector3::new(
+        rng.sample(StandardNormal),
+        rng.sample(StandardNormal),
+        rng.sample(StandardNormal),
+    )
+}
@@ -0,0 +1,127 @@
+//! Collision statistics of the DSMC collision boxes.
+//!
+//! `collisions.csv` holds one row per occupied box and output step, with columns
+//! `step, time, box, collisions, atoms, particles`, where `box` is the index of the box in the grid
+//! of collision boxes, see `crate::collisions::box_index`, `atoms` the number of real atoms and
+//! `particles` the number of simulated particles in it. In the binary format, `collisions.bin`
+//! holds the same rows as 40 byte little-endian records:
+//! `u64 step, f64 time, f64 atoms, i64 box, i32 collisions, i32 particles`. Every field is aligned
+//! to its size, so the file can be read with eg `numpy.fromfile` or memory-mapped as an array of
+//! records.
+//!
+//! The `constant` collision model of `lib::collisions` does not record which box the statistics
+//! belong to, so with it the `box` column is left out, and the binary records are 32 bytes long.
+//!
+//! `collision_totals.csv` sums each step over all boxes:
+//! `step, time, boxes, collisions, atoms, particles, three_body_lost, three_body_rate_Hz`, where
+//! `three_body_lost` is the number of particles lost to three-body recombination since the previous
+//! row and `three_body_rate_Hz` the largest loss rate of the boxes in the step. The last two are
+//! empty without three-body loss.
+
+use lib::collisions::CollisionsTracker;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+
+use crate::collisions::CollisionBoxes;
+use crate::output::{RunDirectory, TableFormat};
+use crate::three_body::ThreeBodyTracker;
+
+/// Writes the statistics of the collision boxes and their totals.
+pub struct CollisionStatsOutput {
+    boxes: BufWriter<File>,
+    totals: BufWriter<File>,
+    format: TableFormat,
+    /// Particles lost to three-body recombination up to the previous row.
+    three_body_reported: u64,
+}
+
+impl CollisionStatsOutput {
+    /// Creates the output files of a run in which `three_body_lost` particles were already lost
+    /// to three-body recombination, nonzero for resumed runs. The `box` column is only written
+    /// with `box_indices`, when the collision model records them.
+    pub fn new(
+        run_dir: &RunDirectory,
+        format: TableFormat,
+        box_indices: bool,
+        three_body_lost: u64,
+    ) -> Result<Self, Error> {
+        let boxes = match format {
+            TableFormat::Csv => {
+                let mut boxes = BufWriter::new(File::create(run_dir.file("collisions.csv"))?);
+                let box_column = if box_indices { "box," } else { "" };
+                writeln!(boxes, "step,time,{}collisions,atoms,particles", box_column)?;
+                boxes
+            }
+            TableFormat::Binary => BufWriter::new(File::create(run_dir.file("collisions.bin"))?),
+        };
+        let mut totals = BufWriter::new(File::create(run_dir.file("collision_totals.csv"))?);
+        writeln!(
+            totals,
+            "step,time,boxes,collisions,atoms,particles,three_body_lost,three_body_rate_Hz"
+        )?;
+        Ok(CollisionStatsOutput {
+            boxes,
+            totals,
+            format,
+            three_body_reported: three_body_lost,
+        })
+    }
+
+    /// Writes the statistics of the last step, `step`, ending at `time`, with the indices of the
+    /// boxes if they are recorded and the three-body losses if they are simulated.
+    pub fn write(
+        &mut self,
+        step: u64,
+        time: f64,
+        tracker: &CollisionsTracker,
+        indices: Option<&CollisionBoxes>,
+        three_body: Option<&ThreeBodyTracker>,
+    ) -> Result<(), Error> {
+        let rows = tracker
+            .num_collisions
+            .iter()
+            .zip(tracker.num_atoms.iter())
+            .zip(tracker.num_particles.iter());
+        for (row, ((collisions, atoms), particles)) in rows.enumerate() {
+            let index = indices.map(|indices| indices.0[row]);
+            match self.format {
+                TableFormat::Csv => {
+                    write!(self.boxes, "{},{},", step, time)?;
+                    if let Some(index) = index {
+                        write!(self.boxes, "{},", index)?;
+                    }
+                    writeln!(self.boxes, "{},{},{}", collisions, atoms, particles)?;
+                }
+                TableFormat::Binary => {
+                    self.boxes.write_all(&step.to_le_bytes())?;
+                    self.boxes.write_all(&time.to_le_bytes())?;
+                    self.boxes.write_all(&atoms.to_le_bytes())?;
+                    if let Some(index) = index {
+                        self.boxes.write_all(&index.to_le_bytes())?;
+                    }
+                    self.boxes.write_all(&collisions.to_le_bytes())?;
+                    self.boxes.write_all(&particles.to_le_bytes())?;
+                }
+            }
+        }
+        let three_body = match three_body {
+            Some(three_body) => {
+                let lost = three_body.total_lost - self.three_body_reported;
+                self.three_body_reported = three_body.total_lost;
+                format!("{},{}", lost, three_body.peak_rate)
+            }
+            None => ",".to_string(),
+        };
+        writeln!(
+            self.totals,
+            "{},{},{},{},{},{},{}",
+            step,
+            time,
+            tracker.num_collisions.len(),
+            tracker.num_collisions.iter().map(|&n| n as i64).sum::<i64>(),
+            tracker.num_atoms.iter().fold(0.0, |total, n| total + n),
+            tracker.num_particles.iter().map(|&n| n as i64).sum::<i64>(),
+            three_body
+        )
+    }
+}
@@ -0,0 +1,265 @@
+//! Collisions with an energy-dependent s-wave cross section.
+//!
+//! The `s_wave` collision model replaces the constant cross section of `lib::collisions` with that
+//! of a pair at relative speed `g`, `sigma(k) = f 4 pi a^2 / (1 + k^2 a^2)` with the relative wave
+//! vector `k = mu g / hbar`. It tends to `f 4 pi a^2` at low energy and to the unitarity limit
+//! `f 4 pi / k^2` at high energy. The symmetry factor `f` is 2 for identical bosons, 0 for identical
+//! fermions, which do not collide in the s-wave, and 1 for distinguishable atoms.
+//!
+//! Collisions are drawn in the same boxes as `lib::collisions`, by the no-time-counter method: in a
+//! box of `N` particles, `N (N - 1) W (sigma g)_max dt / 2V` candidate pairs are drawn, and each
+//! collides with probability `sigma(g) g / (sigma g)_max`, scattering isotropically in the centre of
+//! mass frame. Here `W` is the number of atoms per particle and `(sigma g)_max` a bound on the
+//! collision rate of the pairs in the box. The candidates are drawn from the run's seed, so the
+//! collisions are reproducible.
+
+use lib::atom::{Atom, Position, Velocity};
+use lib::collisions::{ApplyCollisionsOption, CollisionParameters, CollisionsTracker};
+use lib::constant::{AMU, BOLTZCONST, HBAR, PI};
+use lib::integrator::Timestep;
+use nalgebra::Vector3;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, UnitSphere};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::species::{Species, Statistics, BOHR_RADIUS};
+
+/// Upper limit of `mu g^2 / 2kT` in [SWaveCrossSection::thermal_rate], beyond which the thermal
+/// distribution of relative speeds is negligible.
+const THERMAL_AVERAGE_CUTOFF: f64 = 40.0;
+/// Points of the integral in [SWaveCrossSection::thermal_rate].
+const THERMAL_AVERAGE_POINTS: usize = 1000;
+
+/// How the cross section of a colliding pair is calculated.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionModel {
+    /// The constant cross section of `lib::collisions`.
+    #[default]
+    Constant,
+    /// The s-wave cross section at the relative speed of each pair.
+    SWave,
+}
+
+/// Exchange symmetry of the colliding pairs.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PairSymmetry {
+    Bosons,
+    Fermions,
+    Distinguishable,
+}
+
+impl PairSymmetry {
+    /// The symmetry of a species, with fermions taken to be in a mixture of spin states as for
+    /// the constant cross section of [Species].
+    pub fn of(species: &Species) -> Self {
+        match species.statistics {
+            Statistics::Boson => PairSymmetry::Bosons,
+            Statistics::Fermion => PairSymmetry::Distinguishable,
+        }
+    }
+
+    fn factor(&self) -> f64 {
+        match self {
+            PairSymmetry::Bosons => 2.0,
+            PairSymmetry::Fermions => 0.0,
+            PairSymmetry::Distinguishable => 1.0,
+        }
+    }
+}
+
+/// The s-wave cross section of a pair of atoms.
+#[derive(Clone, Copy, Debug)]
+pub struct SWaveCrossSection {
+    /// Scattering length, in m.
+    scattering_length: f64,
+    /// Reduced mass of the pair, in kg.
+    reduced_mass: f64,
+    symmetry: PairSymmetry,
+}
+
+impl SWaveCrossSection {
+    /// The cross section of two atoms of `mass` (in amu) with `scattering_length` (in Bohr radii).
+    pub fn new(mass: f64, scattering_length: f64, symmetry: PairSymmetry) -> Self {
+        SWaveCrossSection {
+            scattering_length: scattering_length * BOHR_RADIUS,
+            reduced_mass: mass * AMU / 2.0,
+            symmetry,
+        }
+    }
+
+    /// Cross section at relative speed `g` (in m/s), in m^2.
+    pub fn at(&self, g: f64) -> f64 {
+        let a_squared = self.scattering_length.powi(2);
+        let k = self.reduced_mass * g / HBAR;
+        self.symmetry.factor() * 4.0 * PI * a_squared / (1.0 + k * k * a_squared)
+    }
+
+    /// The largest `sigma(g) g` of pairs with relative speeds up to `g_max`, in m^3/s.
+    ///
+    /// `sigma(g) g` increases up to `k a = 1` and decreases beyond it.
+    fn max_rate(&self, g_max: f64) -> f64 {
+        if self.scattering_length == 0.0 {
+            return 0.0;
+        }
+        let g = g_max.min(HBAR / (self.reduced_mass * self.scattering_length.abs()));
+        self.at(g) * g
+    }
+
+    /// Thermal average of `sigma(g) g` over the relative speeds of pairs at `temperature` (in K),
+    /// in m^3/s.
+    ///
+    /// With `x = mu g^2 / 2kT`, the relative speeds are distributed as `2 sqrt(x / pi) exp(-x) dx`,
+    /// which is integrated by the midpoint rule up to [THERMAL_AVERAGE_CUTOFF].
+    fn thermal_rate(&self, temperature: f64) -> f64 {
+        let step = THERMAL_AVERAGE_CUTOFF / THERMAL_AVERAGE_POINTS as f64;
+        (0..THERMAL_AVERAGE_POINTS)
+            .map(|i| {
+                let x = (i as f64 + 0.5) * step;
+                let g = (2.0 * BOLTZCONST * temperature * x / self.reduced_mass).sqrt();
+                self.at(g) * g * 2.0 * (x / PI).sqrt() * (-x).exp() * step
+            })
+            .sum()
+    }
+}
+
+/// Cross section of the collision model, for the collision rate reported by the diagnostics.
+#[derive(Clone, Copy, Debug)]
+pub enum CrossSection {
+    /// The constant cross section of `lib::collisions`, in m^2.
+    Constant(f64),
+    SWave(SWaveCrossSection),
+}
+
+impl CrossSection {
+    /// Thermal average of `sigma(g) g` over the relative speeds `g` of pairs of atoms of `mass`
+    /// (in kg) at `temperature` (in K), in m^3/s.
+    pub fn thermal_rate(&self, mass: f64, temperature: f64) -> f64 {
+        match self {
+            CrossSection::Constant(sigma) => sigma * (16.0 * BOLTZCONST * temperature / (PI * mass)).sqrt(),
+            CrossSection::SWave(cross_section) => cross_section.thermal_rate(temperature),
+        }
+    }
+}
+
+/// Random generator of the collisions and three-body losses, a separate stream of the run's seed.
+pub struct CollisionRng(pub ChaCha8Rng);
+
+/// Index of each box recorded in the [CollisionsTracker] in the last step, as given by [box_index].
+#[derive(Default)]
+pub struct CollisionBoxes(pub Vec<i64>);
+
+/// Collides the atoms with the [SWaveCrossSection], in the boxes given by [CollisionParameters].
+///
+/// The `sigma` of the parameters is unused. Like `lib::collisions`, the system only runs if the
+/// `ApplyCollisionsOption` resource is present, and records the collisions of each occupied box in
+/// the [CollisionsTracker], and the index of the box in [CollisionBoxes].
+pub struct SWaveCollisionSystem {
+    pub cross_section: SWaveCrossSection,
+}
+
+impl<'a> System<'a> for SWaveCollisionSystem {
+    type SystemData = (
+        ReadStorage<'a, Atom>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        Option<Read<'a, ApplyCollisionsOption>>,
+        ReadExpect<'a, Timestep>,
+        ReadExpect<'a, CollisionParameters>,
+        WriteExpect<'a, CollisionsTracker>,
+        WriteExpect<'a, CollisionBoxes>,
+        WriteExpect<'a, CollisionRng>,
+    );
+
+    fn run(
+        &mut self,
+        (atoms, positions, mut velocities, apply, timestep, params, mut tracker, mut indices, mut rng): Self::SystemData,
+    ) {
+        if apply.is_none() {
+            return;
+        }
+        let mut boxes: BTreeMap<i64, Vec<&mut Velocity>> = BTreeMap::new();
+        for (_, position, velocity) in (&atoms, &positions, &mut velocities).join() {
+            if let Some(index) = box_index(&position.pos, params.box_number, params.box_width) {
+                boxes.entry(index).or_default().push(velocity);
+            }
+        }
+
+        let volume = params.box_width.powi(3);
+        tracker.num_collisions.clear();
+        tracker.num_atoms.clear();
+        tracker.num_particles.clear();
+        indices.0.clear();
+        for (&index, velocities) in boxes.iter_mut() {
+            let collisions = self.collide(velocities, &params, volume, timestep.delta, &mut rng.0);
+            tracker.num_collisions.push(collisions);
+            tracker.num_atoms.push(velocities.len() as f64 * params.macroparticle);
+            tracker.num_particles.push(velocities.len() as i32);
+            indices.0.push(index);
+        }
+    }
+}
+
+impl SWaveCollisionSystem {
+    /// Collides the particles of one box during `dt`, returning the number of collisions.
+    fn collide(
+        &self,
+        velocities: &mut [&mut Velocity],
+        params: &CollisionParameters,
+        volume: f64,
+        dt: f64,
+        rng: &mut ChaCha8Rng,
+    ) -> i32 {
+        let n = velocities.len();
+        if n < 2 {
+            return 0;
+        }
+        let mean = velocities.iter().map(|v| v.vel).sum::<Vector3<f64>>() / n as f64;
+        let g_max = 2.0 * velocities.iter().map(|v| (v.vel - mean).norm()).fold(0.0, f64::max);
+        let max_rate = self.cross_section.max_rate(g_max);
+        if max_rate == 0.0 {
+            return 0;
+        }
+
+        let mut candidates = 0.5 * (n * (n - 1)) as f64 * params.macroparticle * max_rate * dt / volume;
+        candidates = candidates.min(params.collision_limit);
+        let mut collisions = 0;
+        while candidates > 0.0 {
+            if candidates < 1.0 && rng.gen::<f64>() >= candidates {
+                break;
+            }
+            candidates -= 1.0;
+            let i = rng.gen_range(0..n);
+            let j = (i + rng.gen_range(1..n)) % n;
+            let relative = velocities[i].vel - velocities[j].vel;
+            let g = relative.norm();
+            if rng.gen::<f64>() * max_rate >= self.cross_section.at(g) * g {
+                continue;
+            }
+            let centre_of_mass = (velocities[i].vel + velocities[j].vel) / 2.0;
+            let direction = Vector3::from(UnitSphere.sample(rng));
+            velocities[i].vel = centre_of_mass + direction * (g / 2.0);
+            velocities[j].vel = centre_of_mass - direction * (g / 2.0);
+            collisions += 1;
+        }
+        collisions
+    }
+}
+
+/// Index of the collision box containing `pos`, in a cube of `box_number`^3 boxes of `box_width`
+/// centred on the origin, or `None` outside it.
+pub(crate) fn box_index(pos: &Vector3<f64>, box_number: i64, box_width: f64) -> Option<i64> {
+    let mut index = 0;
+    for i in (0..3).rev() {
+        let cell = (pos[i] / box_width + box_number as f64 / 2.0).floor();
+        if !(cell >= 0.0 && cell < box_number as f64) {
+            return None;
+        }
+        index = index * box_number + cell as i64;
+    }
+    Some(index)
+}
@@ -0,0 +1,492 @@
+//! Declarative description of an evaporation experiment.
+//!
+//! An experiment is written as a TOML file and parsed into the typed structs below. Parsing
+//! rejects unknown keys, and `validate` checks the physical sanity of the values, so every
+//! error names the key that caused it, eg `beams[1].waist must be positive`.
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cloud::CloudDistribution;
+use crate::collisions::{CollisionModel, PairSymmetry};
+use crate::output::{is_plain_name, SnapshotFormat, TableFormat};
+use crate::ramp::BeamRamp;
+use crate::species::{Species, Statistics, Transition};
+use crate::volume::VolumeConfig;
+use crate::BEAM_NUMBER;
+
+/// Top level experiment description.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    pub simulation: SimulationConfig,
+    pub beams: Vec<BeamConfig>,
+    #[serde(default)]
+    pub ramp: RampConfig,
+    pub atoms: AtomCloudConfig,
+    pub collisions: CollisionConfig,
+    /// Removal of atoms energetic enough to escape the trap; only the simulation volume removes
+    /// atoms if omitted.
+    #[serde(default)]
+    pub evaporation: Option<EvaporationConfig>,
+    /// Gravity acting on the atoms; there is none if omitted.
+    #[serde(default)]
+    pub gravity: Option<GravityConfig>,
+    /// Atoms leaving the simulation volume are deleted.
+    pub volume: VolumeConfig,
+    pub output: OutputConfig,
+}
+
+/// Integration settings.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SimulationConfig {
+    /// Integration timestep, in s.
+    pub timestep: f64,
+    /// Number of integration steps to run.
+    pub steps: u64,
+    /// Seed of the random generator used to sample the initial cloud, drawn at random if omitted.
+    /// Given as an integer, or as a string for seeds above 2^63 that TOML integers cannot hold.
+    #[serde(default, deserialize_with = "deserialize_seed")]
+    pub seed: Option<u64>,
+}
+
+/// A single gaussian dipole beam.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BeamConfig {
+    /// Wavelength of the beam, in m.
+    pub wavelength: f64,
+    /// 1/e^2 intensity radius at the focus, in m.
+    pub waist: f64,
+    /// Power at the start of the simulation, in W.
+    pub power: f64,
+    /// Propagation direction, normalised on use.
+    pub direction: [f64; 3],
+    /// Position of the focus, in m.
+    #[serde(default)]
+    pub intersection: [f64; 3],
+    #[serde(default)]
+    pub ellipticity: f64,
+    /// Ramps of the beam parameters, starting from the values above. The beam is constant if omitted.
+    pub ramp: Option<BeamRamp>,
+}
+
+/// How the beam ramps are applied.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RampConfig {
+    #[serde(default)]
+    pub mode: RampMode,
+    /// Maximum spacing of the ramp keyframes in `keyframes` mode, in s. Defaults to the timestep.
+    pub resolution: Option<f64>,
+}
+
+/// How the ramp is applied to the beams.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RampMode {
+    /// Evaluate the power in closed form every step.
+    #[default]
+    Analytic,
+    /// Interpolate between precomputed `Ramp<GaussianBeam>` keyframes.
+    Keyframes,
+}
+
+/// The initial atom cloud.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AtomCloudConfig {
+    /// Number of simulated (macro)particles.
+    pub number: u64,
+    /// Name of a species in `crate::species`, eg `88Sr`, which sets the mass, the transitions and
+    /// the scattering length. A custom species is described by the fields below instead.
+    pub species: Option<String>,
+    /// Atomic mass, in amu.
+    pub mass: Option<f64>,
+    /// Wavelength of the transition used to calculate the polarizability, in m.
+    pub transition_wavelength: Option<f64>,
+    /// Linewidth of that transition, in s^-1.
+    pub transition_linewidth: Option<f64>,
+    /// Transitions used to calculate the polarizability, instead of the single transition above.
+    pub transitions: Option<Vec<Transition>>,
+    /// Include the counter-rotating terms in the polarizability; they are neglected in the
+    /// rotating-wave approximation.
+    #[serde(default = "default_true")]
+    pub counter_rotating: bool,
+    /// s-wave scattering length, in Bohr radii. Overrides that of `species`, eg for a species
+    /// whose scattering length is tuned by a magnetic field.
+    pub scattering_length: Option<f64>,
+    /// Quantum statistics of a custom species, bosons if omitted.
+    pub statistics: Option<Statistics>,
+    /// Three-body loss coefficient `L3`, in m^6/s. Overrides that of `species`; there is no
+    /// three-body loss if neither gives one.
+    pub three_body_loss: Option<f64>,
+    pub distribution: CloudDistribution,
+}
+
+/// Parameters of the DSMC collision model, see `lib::collisions::CollisionParameters`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CollisionConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// The number of real particles per simulated particle.
+    pub macroparticle: f64,
+    pub box_number: i64,
+    /// Width of a collision box, in m.
+    pub box_width: f64,
+    /// Collisional cross section of a custom species, in m^2. For other species it is calculated
+    /// from the scattering length.
+    pub sigma: Option<f64>,
+    /// Maximum number of collisions that can be calculated in one frame.
+    pub collision_limit: f64,
+    /// How the cross section of a colliding pair is calculated, see `crate::collisions`.
+    #[serde(default)]
+    pub model: CollisionModel,
+    /// Exchange symmetry of the colliding pairs in the `s_wave` model; follows the statistics of
+    /// the species if omitted.
+    pub symmetry: Option<PairSymmetry>,
+    /// Number of steps between writes of the collision statistics; defaults to `output.interval`.
+    pub output_interval: Option<u64>,
+    /// Format of the per-box collision statistics, see `crate::collision_stats`.
+    #[serde(default)]
+    pub output_format: TableFormat,
+}
+
+/// Uniform gravitational acceleration.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GravityConfig {
+    /// Direction in which gravity pulls, normalised on use.
+    #[serde(default = "default_gravity_direction")]
+    pub direction: [f64; 3],
+    /// In m/s^2.
+    #[serde(default = "default_gravity_magnitude")]
+    pub magnitude: f64,
+}
+
+fn default_gravity_direction() -> [f64; 3] {
+    [0.0, 0.0, -1.0]
+}
+
+fn default_gravity_magnitude() -> f64 {
+    9.80665
+}
+
+/// Settings of the energy cut, see `crate::evaporation`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EvaporationConfig {
+    /// Only remove atoms above the escape energy once they are further than this many beam waists
+    /// from the trap centre; atoms are removed as soon as their energy allows them to escape if omitted.
+    pub waists: Option<f64>,
+}
+
+/// Where and how often data is written.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Directory under which each run creates its own output directory.
+    pub directory: PathBuf,
+    /// Name of the run directory; generated from the start time if omitted.
+    pub name: Option<String>,
+    /// Replace the contents of an existing run directory of the same name.
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Number of steps between writes of positions, velocities and intensities.
+    pub interval: u64,
+    #[serde(default)]
+    pub format: SnapshotFormat,
+    /// Number of steps between checkpoints the run can be resumed from; none are written if omitted.
+    pub checkpoint_interval: Option<u64>,
+    /// Number of steps between rows of the diagnostics file; defaults to `interval`.
+    pub diagnostics_interval: Option<u64>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn deserialize_seed<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seed {
+        Integer(u64),
+        Text(String),
+    }
+    match Seed::deserialize(deserializer)? {
+        Seed::Integer(seed) => Ok(Some(seed)),
+        Seed::Text(text) => text
+            .parse()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid seed `{}`", text))),
+    }
+}
+
+/// Error raised while loading an experiment file.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid { key: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Invalid { key, message } => write!(f, "{} {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub(crate) fn invalid(key: impl Into<String>, message: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.into(),
+        message: message.to_string(),
+    }
+}
+
+pub(crate) fn positive(key: impl Into<String>, value: f64) -> Result<(), ConfigError> {
+    if value > 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(key, "must be positive"))
+    }
+}
+
+pub(crate) fn non_negative(key: impl Into<String>, value: f64) -> Result<(), ConfigError> {
+    if value >= 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(key, "must not be negative"))
+    }
+}
+
+impl ExperimentConfig {
+    /// Gravitational acceleration acting on the atoms, in m/s^2.
+    pub fn gravity(&self) -> Vector3<f64> {
+        match &self.gravity {
+            Some(gravity) => Vector3::from(gravity.direction).normalize() * gravity.magnitude,
+            None => Vector3::zeros(),
+        }
+    }
+
+    /// The species of the atoms, from the table of known species or the properties given in
+    /// `atoms`, with the collisional cross section given by `collisions.sigma` for custom species.
+    pub fn species(&self) -> Result<Species, ConfigError> {
+        let atoms = &self.atoms;
+        let mut species = match &atoms.species {
+            Some(name) => {
+                let species = Species::known(name).ok_or_else(|| {
+                    invalid("atoms.species", &format!("must be one of {}", Species::NAMES.join(", ")))
+                })?;
+                let explicit = [
+                    ("atoms.mass", atoms.mass.is_some()),
+                    ("atoms.transition_wavelength", atoms.transition_wavelength.is_some()),
+                    ("atoms.transition_linewidth", atoms.transition_linewidth.is_some()),
+                    ("atoms.transitions", atoms.transitions.is_some()),
+                    ("atoms.statistics", atoms.statistics.is_some()),
+                    ("collisions.sigma", self.collisions.sigma.is_some()),
+                ];
+                if let Some((key, _)) = explicit.iter().find(|(_, given)| *given) {
+                    return Err(invalid(*key, "is set by atoms.species and must be omitted"));
+                }
+                species
+            }
+            None => {
+                let required = |key: &str, value: Option<f64>| {
+                    let value = value.ok_or_else(|| invalid(key, "is required unless atoms.species is given"))?;
+                    positive(key, value)?;
+                    Ok::<f64, ConfigError>(value)
+                };
+                let transitions = match &atoms.transitions {
+                    Some(transitions) => {
+                        if atoms.transition_wavelength.is_some() || atoms.transition_linewidth.is_some() {
+                            return Err(invalid(
+                                "atoms.transitions",
+                                "cannot be given with atoms.transition_wavelength and atoms.transition_linewidth",
+                            ));
+                        }
+                        if transitions.is_empty() {
+                            return Err(invalid("atoms.transitions", "must list at least one transition"));
+                        }
+                        for (i, transition) in transitions.iter().enumerate() {
+                            positive(format!("atoms.transitions[{}].wavelength", i), transition.wavelength)?;
+                            positive(format!("atoms.transitions[{}].linewidth", i), transition.linewidth)?;
+                            if transition.ground_degeneracy == 0 {
+                                return Err(invalid(format!("atoms.transitions[{}].ground_degeneracy", i), "must be at least 1"));
+                            }
+                            if transition.excited_degeneracy == 0 {
+                                return Err(invalid(format!("atoms.transitions[{}].excited_degeneracy", i), "must be at least 1"));
+                            }
+                        }
+                        transitions.clone()
+                    }
+                    None => vec![Transition {
+                        wavelength: required("atoms.transition_wavelength", atoms.transition_wavelength)?,
+                        linewidth: required("atoms.transition_linewidth", atoms.transition_linewidth)?,
+                        ground_degeneracy: 1,
+                        excited_degeneracy: 3,
+                    }],
+                };
+                let mut species = Species {
+                    name: "custom".to_string(),
+                    mass: required("atoms.mass", atoms.mass)?,
+                    transitions,
+                    background_polarizability: 0.0,
+                    scattering_length: None,
+                    statistics: atoms.statistics.unwrap_or_default(),
+                    cross_section: 0.0,
+                    three_body_loss: None,
+                };
+                match (self.collisions.sigma, atoms.scattering_length) {
+                    (Some(sigma), None) => {
+                        non_negative("collisions.sigma", sigma)?;
+                        species.cross_section = sigma;
+                    }
+                    (None, Some(_)) => {}
+                    (Some(_), Some(_)) => {
+                        return Err(invalid("collisions.sigma", "must be omitted when atoms.scattering_length is given"))
+                    }
+                    (None, None) => {
+                        return Err(invalid(
+                            "collisions.sigma",
+                            "is required unless atoms.species or atoms.scattering_length is given",
+                        ))
+                    }
+                }
+                species
+            }
+        };
+        if let Some(scattering_length) = atoms.scattering_length {
+            if !scattering_length.is_finite() {
+                return Err(invalid("atoms.scattering_length", "must be finite"));
+            }
+            species = species.with_scattering_length(scattering_length);
+        }
+        if let Some(three_body_loss) = atoms.three_body_loss {
+            non_negative("atoms.three_body_loss", three_body_loss)?;
+            species.three_body_loss = Some(three_body_loss);
+        }
+        Ok(species)
+    }
+
+    /// Reads, parses and validates the experiment file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let config: ExperimentConfig =
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that all values are physically meaningful.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        positive("simulation.timestep", self.simulation.timestep)?;
+        if self.simulation.steps == 0 {
+            return Err(invalid("simulation.steps", "must be at least 1"));
+        }
+
+        if self.beams.len() != BEAM_NUMBER {
+            return Err(invalid(
+                "beams",
+                &format!("must list exactly {} beams, found {}", BEAM_NUMBER, self.beams.len()),
+            ));
+        }
+        for (i, beam) in self.beams.iter().enumerate() {
+            positive(format!("beams[{}].wavelength", i), beam.wavelength)?;
+            // The atoms' polarizability is calculated for a single trapping wavelength.
+            if beam.wavelength != self.beams[0].wavelength {
+                return Err(invalid(
+                    format!("beams[{}].wavelength", i),
+                    "must match the wavelength of beams[0]",
+                ));
+            }
+            positive(format!("beams[{}].waist", i), beam.waist)?;
+            non_negative(format!("beams[{}].power", i), beam.power)?;
+            let norm = beam.direction.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if !(norm > 0.0 && norm.is_finite()) {
+                return Err(invalid(format!("beams[{}].direction", i), "must be a non-zero vector"));
+            }
+            if let Some(ramp) = &beam.ramp {
+                ramp.validate(&format!("beams[{}].ramp", i))?;
+            }
+        }
+
+        if let Some(resolution) = self.ramp.resolution {
+            positive("ramp.resolution", resolution)?;
+        }
+
+        if self.atoms.number == 0 {
+            return Err(invalid("atoms.number", "must be at least 1"));
+        }
+        let species = self.species()?;
+        self.atoms.distribution.validate("atoms.distribution")?;
+
+        positive("collisions.macroparticle", self.collisions.macroparticle)?;
+        if self.collisions.box_number <= 0 {
+            return Err(invalid("collisions.box_number", "must be positive"));
+        }
+        positive("collisions.box_width", self.collisions.box_width)?;
+        positive("collisions.collision_limit", self.collisions.collision_limit)?;
+        match self.collisions.model {
+            CollisionModel::Constant => {
+                if self.collisions.symmetry.is_some() {
+                    return Err(invalid("collisions.symmetry", "only applies to the s_wave model"));
+                }
+            }
+            CollisionModel::SWave => {
+                if species.scattering_length.is_none() {
+                    return Err(invalid(
+                        "collisions.model",
+                        "s_wave requires atoms.species or atoms.scattering_length",
+                    ));
+                }
+            }
+        }
+        if self.collisions.output_interval == Some(0) {
+            return Err(invalid("collisions.output_interval", "must be at least 1"));
+        }
+
+        if let Some(gravity) = &self.gravity {
+            let norm = gravity.direction.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if !(norm > 0.0 && norm.is_finite()) {
+                return Err(invalid("gravity.direction", "must be a non-zero vector"));
+            }
+            non_negative("gravity.magnitude", gravity.magnitude)?;
+        }
+        if let Some(evaporation) = &self.evaporation {
+            if let Some(waists) = evaporation.waists {
+                non_negative("evaporation.waists", waists)?;
+            }
+        }
+        self.volume.validate("volume")?;
+
+        if let Some(name) = &self.output.name {
+            if !is_plain_name(name) {
+                return Err(invalid("output.name", "must be a plain directory name"));
+            }
+        }
+        if self.output.interval == 0 {
+            return Err(invalid("output.interval", "must be at least 1"));
+        }
+        if self.output.checkpoint_interval == Some(0) {
+            return Err(invalid("output.checkpoint_interval", "must be at least 1"));
+        }
+        if self.output.diagnostics_interval == Some(0) {
+            return Err(invalid("output.diagnostics_interval", "must be at least 1"));
+        }
+        Ok(())
+    }
+}
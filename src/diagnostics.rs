@@ -0,0 +1,265 @@
+//! Thermodynamic observables of the cloud, measured during the simulation.
+//!
+//! The cloud is treated as a thermal gas in a harmonic trap: the temperature along each axis is
+//! taken from the velocity variance, and the peak density from the covariance of the positions,
+//! `n0 = N / ((2 pi)^(3/2) sqrt(det S))`. Each simulated atom stands for `macroparticle` real
+//! atoms.
+//!
+//! The progress of evaporation is measured by the truncation parameter `eta = U / kT`, with `U`
+//! the current trap depth, and by the efficiency `gamma = -d ln(PSD) / d ln(N)`, calculated
+//! between consecutive rows and over the whole run.
+
+use lib::atom::{Atom, Mass, Position, Velocity};
+use lib::constant::{AMU, BOLTZCONST, HBAR, PI};
+use lib::destructor::ToBeDestroyed;
+use lib::integrator::{Step, Timestep};
+use lib::laser::gaussian::GaussianBeam;
+use nalgebra::{Matrix3, Vector3};
+use specs::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::collisions::CrossSection;
+use crate::trap::TrapProperties;
+
+/// Observables of the cloud at one instant.
+#[derive(Clone, Copy, Debug)]
+pub struct CloudObservables {
+    /// Number of real atoms.
+    pub atom_number: f64,
+    pub simulated_atoms: usize,
+    /// Temperature along x, y, z, in K.
+    pub temperature: Vector3<f64>,
+    /// In m^-3.
+    pub peak_density: f64,
+    /// Mean elastic collision rate per atom, `n0 <sigma g> / 2^(3/2)` with the thermal average over
+    /// the relative speeds `g` of pairs, `n0 sigma v / 2` for a constant cross section and the mean
+    /// thermal speed `v`, in s^-1.
+    pub collision_rate: f64,
+    /// Peak phase-space density `n0 lambda^3`.
+    pub phase_space_density: f64,
+}
+
+impl CloudObservables {
+    /// Measures the cloud of simulated atoms at `positions` with `velocities`, of `mass` (in amu),
+    /// colliding with `cross_section`. Returns `None` for fewer than two atoms.
+    pub fn measure(
+        positions: &[Vector3<f64>],
+        velocities: &[Vector3<f64>],
+        mass: f64,
+        macroparticle: f64,
+        cross_section: &CrossSection,
+    ) -> Option<Self> {
+        let simulated_atoms = positions.len();
+        if simulated_atoms < 2 {
+            return None;
+        }
+        let mass = mass * AMU;
+        let position_covariance = covariance(positions);
+        let velocity_covariance = covariance(velocities);
+
+        let atom_number = simulated_atoms as f64 * macroparticle;
+        let temperature = velocity_covariance.diagonal() * mass / BOLTZCONST;
+        let mean_temperature = temperature.mean();
+        let peak_density = atom_number / ((2.0 * PI).powf(1.5) * position_covariance.determinant().sqrt());
+        let de_broglie_wavelength = 2.0 * PI * HBAR / (2.0 * PI * mass * BOLTZCONST * mean_temperature).sqrt();
+
+        Some(CloudObservables {
+            atom_number,
+            simulated_atoms,
+            temperature,
+            peak_density,
+            collision_rate: peak_density * cross_section.thermal_rate(mass, mean_temperature) / 2.0_f64.powf(1.5),
+            phase_space_density: peak_density * de_broglie_wavelength.powi(3),
+        })
+    }
+
+    /// Mean of the temperatures along the three axes, in K.
+    pub fn mean_temperature(&self) -> f64 {
+        self.temperature.mean()
+    }
+
+    /// Efficiency `-ln(PSD / PSD0) / ln(N / N0)` of the evaporation from `initial` to this state,
+    /// or `None` if no atoms were lost.
+    pub fn efficiency_since(&self, initial: &CloudObservables) -> Option<f64> {
+        let log_number = (self.atom_number / initial.atom_number).ln();
+        if log_number == 0.0 {
+            return None;
+        }
+        Some(-(self.phase_space_density / initial.phase_space_density).ln() / log_number)
+    }
+}
+
+/// The cloud at a diagnostics step.
+#[derive(Clone, Copy, Debug)]
+pub struct DiagnosticsRecord {
+    pub step: u64,
+    pub cloud: CloudObservables,
+    /// Truncation parameter `U / kT` at the mean temperature.
+    pub eta: f64,
+}
+
+/// The first and latest records of the run, for the summary printed once it completes.
+#[derive(Default)]
+pub struct DiagnosticsHistory {
+    pub initial: Option<DiagnosticsRecord>,
+    pub latest: Option<DiagnosticsRecord>,
+}
+
+/// Prints the change of the cloud over the run and the overall evaporation efficiency.
+pub fn print_summary(history: &DiagnosticsHistory) {
+    let (initial, latest) = match (&history.initial, &history.latest) {
+        (Some(initial), Some(latest)) => (initial, latest),
+        _ => {
+            println!("Too few atoms remained to characterise the cloud.");
+            return;
+        }
+    };
+    println!(
+        "Steps {} to {}: atom number {:.3e} -> {:.3e}, temperature {:.2} -> {:.2} uK",
+        initial.step,
+        latest.step,
+        initial.cloud.atom_number,
+        latest.cloud.atom_number,
+        initial.cloud.mean_temperature() * 1e6,
+        latest.cloud.mean_temperature() * 1e6
+    );
+    let efficiency = match latest.cloud.efficiency_since(&initial.cloud) {
+        Some(efficiency) => format!("{:.2}", efficiency),
+        None => "undefined, no atoms lost".to_string(),
+    };
+    println!(
+        "  phase-space density {:.3e} -> {:.3e}, eta {:.1} -> {:.1}, efficiency {}",
+        initial.cloud.phase_space_density,
+        latest.cloud.phase_space_density,
+        initial.eta,
+        latest.eta,
+        efficiency
+    );
+}
+
+fn covariance(values: &[Vector3<f64>]) -> Matrix3<f64> {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<Vector3<f64>>() / n;
+    values
+        .iter()
+        .map(|v| (v - mean) * (v - mean).transpose())
+        .sum::<Matrix3<f64>>()
+        / (n - 1.0)
+}
+
+/// Writes the [CloudObservables], `eta` and the efficiency since the previous row to a csv file
+/// every `interval` steps, and keeps the [DiagnosticsHistory].
+pub struct DiagnosticsSystem {
+    writer: BufWriter<File>,
+    interval: u64,
+    macroparticle: f64,
+    cross_section: CrossSection,
+    /// Polarizability prefactor of the atoms, for the trap depth.
+    prefactor: f64,
+    /// Gravitational acceleration, in m/s^2.
+    gravity: Vector3<f64>,
+}
+
+impl DiagnosticsSystem {
+    pub fn new(
+        path: &Path,
+        interval: u64,
+        macroparticle: f64,
+        cross_section: CrossSection,
+        prefactor: f64,
+        gravity: Vector3<f64>,
+    ) -> Result<Self, Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(
+            writer,
+            "step,time,atom_number,simulated_atoms,temperature_x_uK,temperature_y_uK,temperature_z_uK,temperature_uK,peak_density_m3,collision_rate_Hz,phase_space_density,eta,efficiency"
+        )?;
+        Ok(DiagnosticsSystem {
+            writer,
+            interval,
+            macroparticle,
+            cross_section,
+            prefactor,
+            gravity,
+        })
+    }
+}
+
+impl<'a> System<'a> for DiagnosticsSystem {
+    type SystemData = (
+        ReadStorage<'a, Atom>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Mass>,
+        ReadStorage<'a, ToBeDestroyed>,
+        ReadStorage<'a, GaussianBeam>,
+        WriteExpect<'a, DiagnosticsHistory>,
+        ReadExpect<'a, Timestep>,
+        ReadExpect<'a, Step>,
+    );
+
+    fn run(
+        &mut self,
+        (atoms, positions, velocities, masses, to_be_destroyed, beams, mut history, timestep, step): Self::SystemData,
+    ) {
+        if step.n % self.interval != 0 {
+            return;
+        }
+        let mut pos = Vec::new();
+        let mut vel = Vec::new();
+        let mut total_mass = 0.0;
+        for (_, position, velocity, mass, _) in (&atoms, &positions, &velocities, &masses, !&to_be_destroyed).join() {
+            pos.push(position.pos);
+            vel.push(velocity.vel);
+            total_mass += mass.value;
+        }
+        let mass = total_mass / pos.len().max(1) as f64;
+
+        let time = step.n as f64 * timestep.delta;
+        match CloudObservables::measure(&pos, &vel, mass, self.macroparticle, &self.cross_section) {
+            Some(cloud) => {
+                let beams: Vec<GaussianBeam> = beams.join().copied().collect();
+                let depth = TrapProperties::calculate(&beams, self.prefactor, mass, self.gravity).depth;
+                let record = DiagnosticsRecord {
+                    step: step.n,
+                    cloud,
+                    eta: depth / (BOLTZCONST * cloud.mean_temperature()),
+                };
+                let efficiency = history
+                    .latest
+                    .and_then(|previous| cloud.efficiency_since(&previous.cloud))
+                    .map_or(String::new(), |efficiency| efficiency.to_string());
+                history.initial.get_or_insert(record);
+                history.latest = Some(record);
+                writeln!(
+                    self.writer,
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                    step.n,
+                    time,
+                    cloud.atom_number,
+                    cloud.simulated_atoms,
+                    cloud.temperature.x * 1e6,
+                    cloud.temperature.y * 1e6,
+                    cloud.temperature.z * 1e6,
+                    cloud.mean_temperature() * 1e6,
+                    cloud.peak_density,
+                    cloud.collision_rate,
+                    cloud.phase_space_density,
+                    record.eta,
+                    efficiency
+                )
+            }
+            None => writeln!(
+                self.writer,
+                "{},{},{},{},,,,,,,,,",
+                step.n,
+                time,
+                pos.len() as f64 * self.macroparticle,
+                pos.len()
+            ),
+        }
+        .expect("Could not write diagnostics file.");
+    }
+}
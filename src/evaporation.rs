@@ -0,0 +1,217 @@
+//! Evaporation: removal of atoms with enough energy to escape the trap.
+//!
+//! Every step, the total energy `E = m v^2 / 2 + U(r)` of each atom is compared with the escape
+//! energy of the trap formed by the beams, the potential at the saddle through which atoms leave
+//! along the axis of one of the beams. Atoms above it are removed, so the cut follows the trap depth
+//! as the beams are ramped, independently of the simulation volume. Under gravity the escape energy
+//! is that of the tilted trap, which is lowered on the side gravity pulls to.
+//!
+//! The trap is calculated again whenever the beams change, except under gravity, where finding the
+//! escape energy of the tilted trap is costly. It is then calculated every [TRAP_INTERVAL] steps and
+//! at every checkpoint instead, so that a resumed run makes the same cuts as the original run.
+
+use lib::atom::{Atom, Mass, Position, Velocity};
+use lib::constant::AMU;
+use lib::destructor::ToBeDestroyed;
+use lib::dipole::Polarizability;
+use lib::integrator::{Step, Timestep};
+use lib::laser::gaussian::GaussianBeam;
+use nalgebra::Vector3;
+use specs::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::trap::{to_microkelvin, TrapProperties};
+
+/// Steps between calculations of the trap of [EnergyCutSystem] under gravity.
+const TRAP_INTERVAL: u64 = 100;
+
+/// Number of atoms removed by the energy cut.
+#[derive(Default)]
+pub struct EvaporationTracker {
+    /// Atoms removed in the last step.
+    pub lost: u64,
+    /// Atoms removed since the start of the run.
+    pub total_lost: u64,
+}
+
+/// Marks atoms whose energy exceeds the escape energy of the trap [ToBeDestroyed].
+pub struct EnergyCutSystem {
+    /// Atoms are only removed further than this many beam waists from the trap minimum.
+    waists: Option<f64>,
+    /// Gravitational acceleration, in m/s^2.
+    gravity: Vector3<f64>,
+    /// Steps between calculations of the trap under gravity, [TRAP_INTERVAL] or a divisor of it
+    /// that also divides the checkpoint interval.
+    interval: u64,
+    /// Steps until the next calculation of the trap under gravity.
+    until_calculation: u64,
+    /// The trap of each species present, kept while the beams are unchanged, or until the next
+    /// scheduled calculation under gravity.
+    traps: Vec<TrapProperties>,
+}
+
+impl EnergyCutSystem {
+    /// Creates the system for a run starting after `start` steps, nonzero for resumed runs.
+    pub fn new(waists: Option<f64>, gravity: Vector3<f64>, start: u64, checkpoint_interval: Option<u64>) -> Self {
+        let mut interval = TRAP_INTERVAL;
+        if let Some(checkpoint_interval) = checkpoint_interval {
+            // Greatest common divisor, so every checkpoint falls on the schedule.
+            let mut remainder = checkpoint_interval;
+            while remainder != 0 {
+                (interval, remainder) = (remainder, interval % remainder);
+            }
+        }
+        EnergyCutSystem {
+            waists,
+            gravity,
+            interval,
+            until_calculation: (interval - start % interval) % interval,
+            traps: Vec::new(),
+        }
+    }
+}
+
+impl<'a> System<'a> for EnergyCutSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, GaussianBeam>,
+        ReadStorage<'a, Atom>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Mass>,
+        ReadStorage<'a, Polarizability>,
+        WriteStorage<'a, ToBeDestroyed>,
+        WriteExpect<'a, EvaporationTracker>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, beams, atoms, positions, velocities, masses, polarizabilities, mut to_be_destroyed, mut tracker): Self::SystemData,
+    ) {
+        let beams: Vec<GaussianBeam> = beams.join().copied().collect();
+        let min_distance = self.waists.map(|waists| {
+            waists * beams.iter().map(|beam| beam.e_radius * 2.0_f64.sqrt()).fold(0.0, f64::max)
+        });
+
+        // The trap depends on the mass and polarizability of the atoms, so it is calculated once
+        // for each species present, and again when the beams change or on the schedule under gravity.
+        if self.gravity == Vector3::zeros() {
+            self.traps.retain(|trap| trap.has_beams(&beams));
+        } else {
+            if self.until_calculation == 0 {
+                self.traps.clear();
+                self.until_calculation = self.interval;
+            }
+            self.until_calculation -= 1;
+        }
+        let traps = &mut self.traps;
+        let mut escaped = Vec::new();
+        for (entity, _, pos, vel, mass, polarizability, _) in (
+            &entities,
+            &atoms,
+            &positions,
+            &velocities,
+            &masses,
+            &polarizabilities,
+            !&to_be_destroyed,
+        )
+            .join()
+        {
+            let index = match traps
+                .iter()
+                .position(|trap| trap.prefactor == polarizability.prefactor && trap.mass == mass.value * AMU)
+            {
+                Some(index) => index,
+                None => {
+                    traps.push(TrapProperties::calculate(
+                        &beams,
+                        polarizability.prefactor,
+                        mass.value,
+                        self.gravity,
+                    ));
+                    traps.len() - 1
+                }
+            };
+            let trap = &traps[index];
+            if let Some(min_distance) = min_distance {
+                if (pos.pos - trap.minimum).norm() < min_distance {
+                    continue;
+                }
+            }
+            let kinetic = 0.5 * trap.mass * vel.vel.norm_squared();
+            if kinetic + trap.potential(&pos.pos) > trap.escape_energy {
+                escaped.push(entity);
+            }
+        }
+
+        tracker.lost = escaped.len() as u64;
+        tracker.total_lost += tracker.lost;
+        for entity in escaped {
+            to_be_destroyed
+                .insert(entity, ToBeDestroyed)
+                .expect("Could not mark evaporated atom.");
+        }
+    }
+}
+
+/// Writes the escape energy and the number of evaporated atoms to a csv file every `interval` steps.
+///
+/// `lost` counts the atoms removed since the previous row.
+pub struct EvaporationOutputSystem {
+    writer: BufWriter<File>,
+    interval: u64,
+    /// Atoms removed since the previous row.
+    lost: u64,
+    /// Polarizability prefactor of the atoms, to express the escape energy as a temperature.
+    prefactor: f64,
+    /// Mass of the atoms, in amu.
+    mass: f64,
+    /// Gravitational acceleration, in m/s^2.
+    gravity: Vector3<f64>,
+}
+
+impl EvaporationOutputSystem {
+    pub fn new(path: &Path, interval: u64, prefactor: f64, mass: f64, gravity: Vector3<f64>) -> Result<Self, Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "step,time,depth_uK,lost,total_lost")?;
+        Ok(EvaporationOutputSystem {
+            writer,
+            interval,
+            lost: 0,
+            prefactor,
+            mass,
+            gravity,
+        })
+    }
+}
+
+impl<'a> System<'a> for EvaporationOutputSystem {
+    type SystemData = (
+        ReadStorage<'a, GaussianBeam>,
+        ReadExpect<'a, EvaporationTracker>,
+        ReadExpect<'a, Timestep>,
+        ReadExpect<'a, Step>,
+    );
+
+    fn run(&mut self, (beams, tracker, timestep, step): Self::SystemData) {
+        self.lost += tracker.lost;
+        if step.n % self.interval != 0 {
+            return;
+        }
+        let beams: Vec<GaussianBeam> = beams.join().copied().collect();
+        let trap = TrapProperties::calculate(&beams, self.prefactor, self.mass, self.gravity);
+        writeln!(
+            self.writer,
+            "{},{},{},{},{}",
+            step.n,
+            step.n as f64 * timestep.delta,
+            to_microkelvin(trap.depth),
+            self.lost,
+            tracker.total_lost
+        )
+        .expect("Could not write evaporation file.");
+        self.lost = 0;
+    }
+}
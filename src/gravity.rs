@@ -0,0 +1,26 @@
+//! Uniform gravity acting on the atoms.
+
+use lib::atom::{Atom, Velocity};
+use lib::integrator::Timestep;
+use nalgebra::Vector3;
+use specs::prelude::*;
+
+/// Accelerates every atom by `gravity` (in m/s^2).
+///
+/// Gravity is applied as a velocity kick of `g dt` each step rather than through `Force`, so it
+/// does not depend on the order of the system relative to the force clearing and integration
+/// systems of atomecs. For a uniform force the two differ only at `O(dt^2)` in position.
+pub struct GravitySystem {
+    pub gravity: Vector3<f64>,
+}
+
+impl<'a> System<'a> for GravitySystem {
+    type SystemData = (ReadStorage<'a, Atom>, WriteStorage<'a, Velocity>, ReadExpect<'a, Timestep>);
+
+    fn run(&mut self, (atoms, mut velocities, timestep): Self::SystemData) {
+        let kick = self.gravity * timestep.delta;
+        for (_, velocity) in (&atoms, &mut velocities).join() {
+            velocity.vel += kick;
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Reading initial atoms from files of phase-space points.
+//!
+//! Two formats are understood: csv files with one atom per line, and the text output of
+//! `FileOutputPlugin`, in which each frame starts with a `step-<n>, <atom number>` header followed
+//! by one `<generation>,<id>: (x,y,z)` line per atom. Positions and velocities of the text output
+//! are written to separate files and matched by entity.
+
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::cloud::PhaseSpacePoint;
+use crate::config::{invalid, ConfigError};
+
+/// Reads atoms from a csv file with columns `x, y, z, vx, vy, vz`.
+///
+/// Blank lines and lines starting with `#` are skipped, as is a header on the first line.
+pub fn read_csv(path: &Path) -> Result<Vec<PhaseSpacePoint>, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    let mut points = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values: Result<Vec<f64>, _> = line.split(',').map(|v| v.trim().parse::<f64>()).collect();
+        let values = match values {
+            Ok(values) => values,
+            Err(_) if i == 0 => continue,
+            Err(_) => return Err(line_error(path, i, "contains a value that is not a number")),
+        };
+        if values.len() != 6 {
+            return Err(line_error(path, i, "must have 6 columns: x, y, z, vx, vy, vz"));
+        }
+        points.push(PhaseSpacePoint {
+            pos: Vector3::new(values[0], values[1], values[2]),
+            vel: Vector3::new(values[3], values[4], values[5]),
+        });
+    }
+    Ok(points)
+}
+
+/// Reads the atoms of the frame at `step`, or of the last frame, from the position and velocity
+/// text output of a previous run.
+pub fn read_frame(
+    positions: &Path,
+    velocities: &Path,
+    step: Option<u64>,
+) -> Result<Vec<PhaseSpacePoint>, ConfigError> {
+    let pos = read_text_frame(positions, step)?;
+    let vel = read_text_frame(velocities, step)?;
+    if pos.step != vel.step {
+        return Err(invalid(
+            velocities.display().to_string(),
+            &format!("ends at step {}, but {} ends at step {}", vel.step, positions.display(), pos.step),
+        ));
+    }
+
+    let vel: HashMap<&str, Vector3<f64>> = vel.atoms.iter().map(|(id, v)| (id.as_str(), *v)).collect();
+    pos.atoms
+        .iter()
+        .map(|(id, position)| match vel.get(id.as_str()) {
+            Some(velocity) => Ok(PhaseSpacePoint {
+                pos: *position,
+                vel: *velocity,
+            }),
+            None => Err(invalid(
+                velocities.display().to_string(),
+                &format!("has no velocity at step {} for atom {}", pos.step, id),
+            )),
+        })
+        .collect()
+}
+
+/// One frame of a `FileOutputPlugin` text file.
+struct TextFrame {
+    step: u64,
+    /// The vector of each atom, labelled by its entity.
+    atoms: Vec<(String, Vector3<f64>)>,
+}
+
+/// Reads the frame at `step`, or the last frame, of a `FileOutputPlugin` text file.
+fn read_text_frame(path: &Path, step: Option<u64>) -> Result<TextFrame, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    let mut frame: Option<TextFrame> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("step-") {
+            let frame_step = header
+                .split(',')
+                .next()
+                .and_then(|n| n.trim().parse::<u64>().ok())
+                .ok_or_else(|| line_error(path, i, "is not a valid frame header"))?;
+            if let (Some(step), Some(previous)) = (step, &frame) {
+                if previous.step == step {
+                    break;
+                }
+            }
+            frame = Some(TextFrame {
+                step: frame_step,
+                atoms: Vec::new(),
+            });
+            continue;
+        }
+
+        let atoms = &mut frame
+            .as_mut()
+            .ok_or_else(|| line_error(path, i, "comes before the first frame header"))?
+            .atoms;
+        let (id, vector) = line
+            .split_once(':')
+            .ok_or_else(|| line_error(path, i, "must have the form `<generation>,<id>: (x,y,z)`"))?;
+        let values: Result<Vec<f64>, _> = vector
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect();
+        match values {
+            Ok(values) if values.len() == 3 => {
+                atoms.push((id.trim().to_string(), Vector3::new(values[0], values[1], values[2])))
+            }
+            _ => return Err(line_error(path, i, "must hold a vector of 3 numbers")),
+        }
+    }
+
+    match (frame, step) {
+        (Some(frame), Some(step)) if frame.step == step => Ok(frame),
+        (_, Some(step)) => Err(invalid(path.display().to_string(), &format!("has no frame at step {}", step))),
+        (Some(frame), None) => Ok(frame),
+        (None, None) => Err(invalid(path.display().to_string(), "contains no frames")),
+    }
+}
+
+fn line_error(path: &Path, index: usize, message: &str) -> ConfigError {
+    invalid(format!("{} line {}", path.display(), index + 1), message)
+}
@@ -1,10 +1,31 @@
 //! Single particle in a cross beam optical dipole trap
 extern crate atomecs as lib;
 extern crate nalgebra;
+
+mod beams;
+mod checkpoint;
+mod cloud;
+mod collision_stats;
+mod collisions;
+mod config;
+mod diagnostics;
+mod evaporation;
+mod gravity;
+mod import;
+mod manifest;
+mod output;
+mod ramp;
+mod seed;
+mod snapshot;
+mod species;
+mod three_body;
+mod trap;
+mod volume;
+
 use lib::atom::{Atom, Force, Mass, Position, Velocity};
-use lib::dipole::{self, DipolePlugin};
+use lib::dipole::DipolePlugin;
 use lib::integrator::Timestep;
-use lib::laser::{self, LaserPlugin};
+use lib::laser::LaserPlugin;
 use lib::laser::gaussian::GaussianBeam;
 use lib::laser::intensity::{LaserIntensitySamplers};
 use lib::output::file::{FileOutputPlugin, Text};
@@ -12,40 +33,179 @@ use lib::simulation::SimulationBuilder;
 use nalgebra::Vector3;
 use specs::prelude::*;
 use std::time::Instant;
+use std::path::PathBuf;
+use std::process;
 use lib::initiate::NewlyCreated;
-use std::fs::File;
-use std::io::{Error, Write};
 use lib::collisions::{CollisionPlugin, ApplyCollisionsOption, CollisionParameters, CollisionsTracker};
-use lib::sim_region::{ SimulationVolume, VolumeType};
-use lib::shapes::Sphere;
-use lib::ramp::{Ramp, RampUpdateSystem};
-
-use easy_ml::matrices::Matrix;
-use easy_ml::distributions::MultivariateGaussian;
-use rand::{Rng, SeedableRng};
-use rand::distributions::{DistIter, Standard};
-use rand_chacha::ChaCha8Rng;
-
-// use lib::gravity::GravityPlugin;
+use lib::ramp::RampUpdateSystem;
+
+
+use beams::{create_beams, BeamPowerOutputSystem};
+use checkpoint::{AtomState, Checkpoint};
+use collision_stats::CollisionStatsOutput;
+use collisions::{
+    CollisionBoxes, CollisionModel, CollisionRng, CrossSection, PairSymmetry, SWaveCollisionSystem, SWaveCrossSection,
+};
+use config::{ExperimentConfig, RampMode};
+use diagnostics::{DiagnosticsHistory, DiagnosticsSystem};
+use evaporation::{EnergyCutSystem, EvaporationOutputSystem, EvaporationTracker};
+use gravity::GravitySystem;
+use manifest::RunManifest;
+use output::{RunDirectory, SnapshotFormat};
+use ramp::AnalyticBeamRampSystem;
+use seed::RunSeed;
+use snapshot::NpySnapshotSystem;
+use three_body::{ThreeBodyLossSystem, ThreeBodyTracker};
+use trap::TrapProperties;
+use volume::{create_volume, VolumeUpdateSystem};
 
 
 const BEAM_NUMBER: usize = 2;
 
 fn main() {
-    let now = Instant::now();
+    let args: Vec<String> = std::env::args().collect();
+    let run_args = match RunArgs::parse(&args[1..]) {
+        Ok(run_args) => run_args,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            eprintln!(
+                "usage: {} run <experiment.toml> [--overwrite] [--seed <u64>] [--run-index <n>]",
+                args[0]
+            );
+            eprintln!("       {} resume <checkpoint.json> [--overwrite]", args[0]);
+            process::exit(2);
+        }
+    };
+
+    let (mut config, seed, checkpoint) = if run_args.resume {
+        let checkpoint = match Checkpoint::load(&run_args.input) {
+            Ok(checkpoint) => checkpoint,
+            Err(err) => {
+                eprintln!("error: cannot read checkpoint {}: {}", run_args.input.display(), err);
+                process::exit(1);
+            }
+        };
+        let mut config = checkpoint.config.clone();
+        // Whether the resumed run may replace an existing directory is decided afresh.
+        config.output.overwrite = false;
+        (config, checkpoint.seed, Some(checkpoint))
+    } else {
+        let mut config = match ExperimentConfig::load(&run_args.input) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        };
+        if run_args.seed.is_some() {
+            config.simulation.seed = run_args.seed;
+        }
+        let seed = RunSeed::resolve(config.simulation.seed, run_args.run_index);
+        config.simulation.seed = Some(seed.base);
+        (config, seed, None)
+    };
+    config.output.overwrite |= run_args.overwrite;
+
+    // Runs of a batch each get their own directory, and resumed runs one next to the original.
+    let run_name = match &checkpoint {
+        Some(checkpoint) => format!("{}_from_{}", checkpoint.run_name, checkpoint.step),
+        None => {
+            let run_name = config.output.name.clone().unwrap_or_else(RunDirectory::default_name);
+            match seed.run_index {
+                Some(index) => format!("{}_{}", run_name, index),
+                None => run_name,
+            }
+        }
+    };
+    let run_dir = match RunDirectory::create(&config.output.directory, &run_name, config.output.overwrite) {
+        Ok(run_dir) => run_dir,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    };
+    println!("Writing output to {}", run_dir.path().display());
+    println!("Random seed {}", seed.seed());
+    if let Some(checkpoint) = &checkpoint {
+        println!("Resuming {} from step {}", checkpoint.run_name, checkpoint.step);
+    }
+    run(&config, &run_dir, seed, checkpoint.as_ref());
+}
 
-                                   //units
-    let wavelength    = 1064.0e-9; //m
-    let e_radius      = 60.0e-6/2.0_f64.sqrt(); //m
+/// Command line arguments of `evaperative_cooling run` and `evaperative_cooling resume`.
+struct RunArgs {
+    /// Whether to resume from a checkpoint rather than start a new run.
+    resume: bool,
+    /// The experiment file, or the checkpoint file when resuming.
+    input: PathBuf,
+    overwrite: bool,
+    /// Overrides `simulation.seed` of the experiment file.
+    seed: Option<u64>,
+    /// Index of this run within a batch, used to derive its seed.
+    run_index: Option<u64>,
+}
 
-    let dt            = 1.0e-6;      //s
-    let sim_length    = 100000;       //none
+impl RunArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter();
+        let resume = match args.next().map(String::as_str) {
+            Some("run") => false,
+            Some("resume") => true,
+            Some(command) => return Err(format!("unknown command `{}`", command)),
+            None => return Err("missing command".to_string()),
+        };
+
+        let mut input = None;
+        let mut overwrite = false;
+        let mut seed = None;
+        let mut run_index = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--overwrite" => overwrite = true,
+                "--seed" | "--run-index" if resume => {
+                    return Err(format!("{} cannot be used when resuming from a checkpoint", arg))
+                }
+                "--seed" => seed = Some(parse_flag_value("--seed", args.next())?),
+                "--run-index" => run_index = Some(parse_flag_value("--run-index", args.next())?),
+                flag if flag.starts_with("--") => return Err(format!("unknown option `{}`", flag)),
+                path if input.is_none() => input = Some(PathBuf::from(path)),
+                extra => return Err(format!("unexpected argument `{}`", extra)),
+            }
+        }
 
-    let initial_power = 7.0;             //W
-    let final_power   = 0.25;          //W
-    let rate          = (  final_power - initial_power   ) / ( 0.5 * (sim_length as f64) * dt ); //0.0;       //W/s
+        Ok(RunArgs {
+            resume,
+            input: input.ok_or(if resume { "missing checkpoint file" } else { "missing experiment file" })?,
+            overwrite,
+            seed,
+            run_index,
+        })
+    }
+}
 
-    let data_rate = 500;
+fn parse_flag_value(flag: &str, value: Option<&String>) -> Result<u64, String> {
+    let value = value.ok_or(format!("{} requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a non-negative integer, got `{}`", flag, value))
+}
+
+fn run(config: &ExperimentConfig, run_dir: &RunDirectory, seed: RunSeed, checkpoint: Option<&Checkpoint>) {
+    let now = Instant::now();
+
+    let dt = config.simulation.timestep;
+    let sim_length = config.simulation.steps;
+    let data_rate = config.output.interval;
+
+    let cloud = &config.atoms;
+    let species = match config.species() {
+        Ok(species) => species,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    };
+    let polarizability = species.polarizability(config.beams[0].wavelength, cloud.counter_rotating);
 
     // Configure simulation output.
     let mut sim_builder = SimulationBuilder::default();
@@ -54,288 +214,274 @@ fn main() {
     sim_builder.add_plugin(LaserPlugin::<{BEAM_NUMBER}>);
     sim_builder.add_plugin(DipolePlugin::<{BEAM_NUMBER}>);
     sim_builder.add_end_frame_systems();
-    sim_builder.add_plugin(CollisionPlugin);
-    sim_builder.add_plugin(FileOutputPlugin::<Position, Text, Atom>::new("D:/data_1/pos_ramp_test_007.txt".to_string(), data_rate));
-    sim_builder.add_plugin(FileOutputPlugin::<Velocity, Text, Atom>::new("D:/data_1/vel_ramp_test_007.txt".to_string(), data_rate));
-    sim_builder.add_plugin(
-        FileOutputPlugin::<
-            LaserIntensitySamplers<{BEAM_NUMBER}>,
-            Text,
-            LaserIntensitySamplers<{BEAM_NUMBER}>>::new(
-                "D:/data_1/intensity_ramp_test_007.txt".to_string(),
-                data_rate
-            )
-    );
-    // sin_builder.add_plugin(GravityPlugin);
-    sim_builder.dispatcher_builder.add(
-        RampUpdateSystem::<GaussianBeam>::default(),
-        "update_comp",
-        &[],
-    );
-
-    let mut sim = sim_builder.build();
-
-    // Creating simulation volume
-    let sphere_pos = Vector3::new(0.0, 0.0, 0.0);
-    let sphere_radius = 60.0e-6;
-
-    sim.world
-        .create_entity()
-        .with(Position { pos: sphere_pos })
-        .with(Sphere {
-            radius: sphere_radius,
-        })
-        .with(SimulationVolume {
-            volume_type: VolumeType::Inclusive,
-        })
-        .build();
-
-    // Creating a mutable frames vector for the ramp
-    let mut frames = vec![];
-
-    // Appending the ramp powers for each frame to the vector, this in the form of a paired list (time, componant value)
-    // for i in 0..sim_length {
-    //     frames.append(
-    //         &mut vec![
-    //             (
-    //                 i as f64 * dt,
-    //                 GaussianBeam {
-    //                     intersection: Vector3::new(0.0, 0.0, 0.0),
-    //                     e_radius:  e_radius,
-    //                     // This is a exponetial ramp down of the power
-    //                     // power: ( initial_power - final_power ) * 2.0_f64.powf( -1.0 * rate * i as f64 * dt ) + final_power,
-    //                     // Linear ramp for testing
-    //                     power: rate * ( i as f64 ) * dt + initial_power,
-    //
-    //                     // power: ( 0.187 / (0.01 * 2.0 * 3.1415) ) * 2.0_f64.powf( - (  i as f64 * dt - 0.1 ).powf(2.0) / ( 2.0 * 0.01_f64.powf(2.0) ) ) + 7.0,
-    //                     direction: Vector3::x(),
-    //                     rayleigh_range: crate::laser::gaussian::calculate_rayleigh_range(&wavelength, &e_radius),
-    //                     ellipticity: 0.0
-    //                 }
-    //             )
-    //         ]
-    //     )
-    // }
-
-
-    for i in 0..sim_length {
-        if i <= (( sim_length as f64 * 0.5) as i32 ) {
-            frames.append(
-                &mut vec![
-                    (
-                        i as f64 * dt,
-                        GaussianBeam {
-                            intersection: Vector3::new(0.0, 0.0, 0.0),
-                            e_radius:  e_radius,
-                            // This is a exponetial ramp down of the power
-                            // power: ( initial_power - final_power ) * 2.0_f64.powf( -1.0 * rate * i as f64 * dt ) + final_power,
-                            // Linear ramp for testing
-                            power: rate * ( i as f64 ) * dt + initial_power,
-
-                            // power: ( 0.187 / (0.01 * 2.0 * 3.1415) ) * 2.0_f64.powf( - (  i as f64 * dt - 0.1 ).powf(2.0) / ( 2.0 * 0.01_f64.powf(2.0) ) ) + 7.0,
-                            direction: Vector3::x(),
-                            rayleigh_range: crate::laser::gaussian::calculate_rayleigh_range(&wavelength, &e_radius),
-                            ellipticity: 0.0
-                        }
-                    )
-                ]
-            )
+    let cross_section = match config.collisions.model {
+        CollisionModel::Constant => {
+            sim_builder.add_plugin(CollisionPlugin);
+            CrossSection::Constant(species.cross_section)
         }
-        else {
-            frames.append(
-                &mut vec![
-                    (
-                        i as f64 * dt,
-                        GaussianBeam {
-                            intersection: Vector3::new(0.0, 0.0, 0.0),
-                            e_radius:  e_radius,
-                            // This is a exponetial ramp down of the power
-                            // power: ( initial_power - final_power ) * 2.0_f64.powf( -1.0 * rate * i as f64 * dt ) + final_power,
-                            // Linear ramp for testing
-                            power: final_power,
-
-                            // power: ( 0.187 / (0.01 * 2.0 * 3.1415) ) * 2.0_f64.powf( - (  i as f64 * dt - 0.1 ).powf(2.0) / ( 2.0 * 0.01_f64.powf(2.0) ) ) + 7.0,
-                            direction: Vector3::x(),
-                            rayleigh_range: crate::laser::gaussian::calculate_rayleigh_range(&wavelength, &e_radius),
-                            ellipticity: 0.0
-                        }
+        CollisionModel::SWave => {
+            let cross_section = SWaveCrossSection::new(
+                species.mass,
+                species.scattering_length.expect("s_wave collisions require a scattering length"),
+                config.collisions.symmetry.unwrap_or_else(|| PairSymmetry::of(&species)),
+            );
+            sim_builder.dispatcher_builder.add(SWaveCollisionSystem { cross_section }, "s_wave_collisions", &[]);
+            CrossSection::SWave(cross_section)
+        }
+    };
+    match config.output.format {
+        SnapshotFormat::Text => {
+            sim_builder.add_plugin(FileOutputPlugin::<Position, Text, Atom>::new(run_dir.file_string("pos.txt"), data_rate));
+            sim_builder.add_plugin(FileOutputPlugin::<Velocity, Text, Atom>::new(run_dir.file_string("vel.txt"), data_rate));
+            sim_builder.add_plugin(
+                FileOutputPlugin::<
+                    LaserIntensitySamplers<{BEAM_NUMBER}>,
+                    Text,
+                    LaserIntensitySamplers<{BEAM_NUMBER}>>::new(
+                        run_dir.file_string("intensity.txt"),
+                        data_rate
                     )
-                ]
-            )
+            );
         }
-
+        SnapshotFormat::Npy => {
+            sim_builder.dispatcher_builder.add(
+                NpySnapshotSystem::<{BEAM_NUMBER}>::new(&run_dir.file("atoms.npy"), data_rate)
+                    .expect("Cannot create file."),
+                "npy_snapshot",
+                &[],
+            );
+        }
+    }
+    if config.gravity.is_some() {
+        sim_builder.dispatcher_builder.add(
+            GravitySystem {
+                gravity: config.gravity(),
+            },
+            "gravity",
+            &[],
+        );
+    }
+    let ramp_system = match config.ramp.mode {
+        RampMode::Analytic => {
+            sim_builder.dispatcher_builder.add(AnalyticBeamRampSystem, "analytic_beam_ramp", &[]);
+            "analytic_beam_ramp"
+        }
+        RampMode::Keyframes => {
+            sim_builder.dispatcher_builder.add(
+                RampUpdateSystem::<GaussianBeam>::default(),
+                "update_comp",
+                &[],
+            );
+            "update_comp"
         }
-
-
-    // Adding beam along the x direction
-    let gaussian_beam = GaussianBeam {
-        intersection: Vector3::new(0.0, 0.0, 0.0),
-        e_radius: e_radius,
-        power: 7.0,
-        direction: Vector3::x(),
-        rayleigh_range: crate::laser::gaussian::calculate_rayleigh_range(&wavelength, &e_radius),
-        ellipticity: 0.0,
     };
+    sim_builder.dispatcher_builder.add(
+        BeamPowerOutputSystem::new(&run_dir.file("beam_power.csv"), config.beams.len(), data_rate)
+            .expect("Cannot create file."),
+        "beam_power_output",
+        &[ramp_system],
+    );
+    sim_builder.dispatcher_builder.add(VolumeUpdateSystem, "volume_update", &[ramp_system]);
+    if let Some(evaporation) = &config.evaporation {
+        sim_builder.dispatcher_builder.add(
+            EnergyCutSystem::new(
+                evaporation.waists,
+                config.gravity(),
+                checkpoint.map_or(0, |checkpoint| checkpoint.step),
+                config.output.checkpoint_interval,
+            ),
+            "energy_cut",
+            &[ramp_system],
+        );
+        sim_builder.dispatcher_builder.add(
+            EvaporationOutputSystem::new(
+                &run_dir.file("evaporation.csv"),
+                data_rate,
+                polarizability.prefactor,
+                species.mass,
+                config.gravity(),
+            )
+            .expect("Cannot create file."),
+            "evaporation_output",
+            &["energy_cut"],
+        );
+    }
 
-    let ramp = Ramp{
-        prev: 0,
-        keyframes: frames,
-    };
+    let three_body_loss = species.three_body_loss.filter(|&coefficient| coefficient > 0.0);
+    if let Some(coefficient) = three_body_loss {
+        // The collision generator is shared with the s-wave collisions, so the order is fixed.
+        let mut dependencies = Vec::new();
+        if config.collisions.model == CollisionModel::SWave {
+            dependencies.push("s_wave_collisions");
+        }
+        if config.evaporation.is_some() {
+            dependencies.push("energy_cut");
+        }
+        sim_builder
+            .dispatcher_builder
+            .add(ThreeBodyLossSystem { coefficient }, "three_body_loss", &dependencies);
+    }
 
-    sim.world
-        .create_entity()
-        .with(gaussian_beam)
-        .with(dipole::DipoleLight { wavelength })
-        .with(laser::frame::Frame {
-            x_vector: Vector3::y(),
-            y_vector: Vector3::z(),
-        })
-        .with(ramp.clone())
-        .build();
-
-    // Adding beam along the y direction
-    let gaussian_beam = GaussianBeam {
-        intersection: Vector3::new(0.0, 0.0, 0.0),
-        e_radius: e_radius,
-        power: 7.0,
-        direction: Vector3::y(),
-        rayleigh_range: crate::laser::gaussian::calculate_rayleigh_range(&wavelength, &e_radius),
-        ellipticity: 0.0,
-    };
+    // Atoms removed by the energy cut or three-body loss are not counted.
+    let mut diagnostics_dependencies = vec![ramp_system];
+    if config.evaporation.is_some() {
+        diagnostics_dependencies.push("energy_cut");
+    }
+    if three_body_loss.is_some() {
+        diagnostics_dependencies.push("three_body_loss");
+    }
+    sim_builder.dispatcher_builder.add(
+        DiagnosticsSystem::new(
+            &run_dir.file("diagnostics.csv"),
+            config.output.diagnostics_interval.unwrap_or(data_rate),
+            config.collisions.macroparticle,
+            cross_section,
+            polarizability.prefactor,
+            config.gravity(),
+        )
+        .expect("Cannot create file."),
+        "diagnostics",
+        &diagnostics_dependencies,
+    );
 
-    sim.world
-        .create_entity()
-        .with(gaussian_beam)
-        .with(dipole::DipoleLight { wavelength })
-        .with(laser::frame::Frame {
-            x_vector: Vector3::x(),
-            y_vector: Vector3::z(),
-        })
-        .with(ramp.clone())
-        .build();
+    let mut sim = sim_builder.build();
+    sim.world.insert(DiagnosticsHistory::default());
+    if config.evaporation.is_some() {
+        sim.world.insert(EvaporationTracker::default());
+    }
+    if three_body_loss.is_some() {
+        sim.world.insert(ThreeBodyTracker::default());
+    }
+    if config.collisions.model == CollisionModel::SWave {
+        sim.world.insert(CollisionBoxes::default());
+    }
 
+    create_beams(&mut sim.world, config);
+    create_volume(&mut sim.world, &config.volume);
 
-    let mut rng = rand::thread_rng();
-    let x: u8 = rng.gen();
     // use a fixed seed random generator from the rand crate
-    let mut random_generator = ChaCha8Rng::seed_from_u64(x.into());
-
-    let cluster_xy = MultivariateGaussian::new(
-            Matrix::column(vec![ 0.0, 0.0 ]),
-            Matrix::from(vec![
-                vec![ 6.390371318625299e-11, 0.0 ],
-                vec![ 0.0, 4.7799785348289716e-04  ]
-                ])
-            );
-
-    let cluster_z = MultivariateGaussian::new(
-            Matrix::column(vec![ 0.0, 0.0 ]),
-            Matrix::from(vec![
-                vec![ 3.195234569049243e-11, 0.0 ],
-                vec![ 0.0, 4.7799785348289716e-04 ]
-                ])
-            );
-
-    // Generate points for each cluster
-    let points = 1;
-    let mut random_numbers: DistIter<Standard, &mut ChaCha8Rng, f64> = (&mut random_generator).sample_iter(Standard);
-
-    // Create a single test atom
-    let atom_number = 2_500;
-    for _ in 0..atom_number {
-
-        let x_points = cluster_xy.draw( &mut random_numbers, points).unwrap();
-        let y_points = cluster_xy.draw( &mut random_numbers, points).unwrap();
-        let z_points = cluster_z.draw( &mut random_numbers, points).unwrap();
+    let mut random_generator = seed.rng();
 
+    let mut manifest = RunManifest::new(config, &species, run_dir, seed);
+    if let Some(checkpoint) = checkpoint {
+        manifest.resumed_from = Some(checkpoint.run_name.clone());
+        manifest.start_step = checkpoint.step;
+    }
+    manifest.write(run_dir).expect("Could not write run manifest.");
+
+    let initial_beams: Vec<GaussianBeam> = config.beams.iter().map(beams::gaussian_beam).collect();
+    let initial_trap =
+        TrapProperties::calculate(&initial_beams, polarizability.prefactor, species.mass, config.gravity());
+    println!(
+        "Species {}, mass {:.3} amu, cross section {:.3e} m^2, polarizability {:.1} a.u. at {:.1} nm",
+        species.name,
+        species.mass,
+        species.cross_section,
+        species::in_atomic_units(&polarizability),
+        config.beams[0].wavelength * 1e9
+    );
+    trap::print_summary(&initial_trap);
+    trap::write_trap_table(&run_dir.file("trap.csv"), config, polarizability.prefactor, species.mass, data_rate)
+        .expect("Could not write trap properties file.");
+
+    let atoms: Vec<AtomState> = match checkpoint {
+        Some(checkpoint) => checkpoint.atoms.clone(),
+        None => {
+            cloud.distribution.print_check(&initial_trap);
+            let points = match cloud.distribution.sample(cloud.number, &initial_trap, &mut random_generator) {
+                Ok(points) => points,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    process::exit(1);
+                }
+            };
+            points
+                .iter()
+                .map(|point| AtomState {
+                    pos: point.pos,
+                    vel: point.vel,
+                    force: Vector3::zeros(),
+                    mass: species.mass,
+                })
+                .collect()
+        }
+    };
+    for atom in atoms {
         sim.world
             .create_entity()
             .with(Atom)
-            .with(Mass { value: 87.0 })
-            .with(Force::new())
-            .with(Position {
-                pos: Vector3::new(
-                    x_points.get(0,0),
-                    y_points.get(0,0),
-                    z_points.get(0,0),
-                ),
-            })
-            .with(Velocity {
-                vel: Vector3::new(
-                    x_points.get(0,1),
-                    y_points.get(0,1),
-                    z_points.get(0,1),
-                ),
-            })
-
-            .with(dipole::Polarizability::calculate_for(
-                wavelength, 461e-9, 2.1e8,
-            ))
+            .with(Mass { value: atom.mass })
+            .with(Force { force: atom.force })
+            .with(Position { pos: atom.pos })
+            .with(Velocity { vel: atom.vel })
+            .with(polarizability)
             .with(lib::initiate::NewlyCreated)
             .build();
     }
 
-    sim.world.insert(ApplyCollisionsOption);
+    let collisions = &config.collisions;
+    if collisions.enabled {
+        sim.world.insert(ApplyCollisionsOption);
+    }
     sim.world.insert(CollisionParameters {
-        macroparticle: 4e2,        //The number of real partcles per particle simulated
-        box_number: 1000,          //Any number large enough to cover entire cloud with collision boxes. Overestimating box number will not affect performance.
-        box_width: 1e-6,           //Too few particles per box will both underestimate collision rate and cause large statistical fluctuations.
-                                   //Boxes must also be smaller than typical length scale of density variations within the cloud, since the collisions model treats gas within a box as homogeneous.
-        sigma: 1.95e-19,           //Approximate collisional cross section of Sr
-        collision_limit: 10_000.0, //Maximum number of collisions that can be calculated in one frame.
-                                   //This avoids absurdly high collision numbers if many atoms are initialised with the same position, for example.
+        macroparticle: collisions.macroparticle, //The number of real partcles per particle simulated
+        box_number: collisions.box_number,       //Any number large enough to cover entire cloud with collision boxes. Overestimating box number will not affect performance.
+        box_width: collisions.box_width,         //Too few particles per box will both underestimate collision rate and cause large statistical fluctuations.
+                                                 //Boxes must also be smaller than typical length scale of density variations within the cloud, since the collisions model treats gas within a box as homogeneous.
+        sigma: species.cross_section,
+        collision_limit: collisions.collision_limit, //Maximum number of collisions that can be calculated in one frame.
+                                                     //This avoids absurdly high collision numbers if many atoms are initialised with the same position, for example.
     });
     sim.world.insert(CollisionsTracker {
         num_collisions: Vec::new(),
         num_atoms: Vec::new(),
         num_particles: Vec::new(),
     });
+    sim.world.insert(CollisionRng(seed.collision_rng()));
 
     // Define timestep
     sim.world.insert(Timestep { delta: dt });
     //Timestep must also be much smaller than mean collision time
 
-    let mut filename = File::create("D:/data_1/collisions_ramp_test_007.txt").expect("Cannot create file.");
+    let mut start = 0;
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.restore(&mut sim.world, &mut random_generator);
+        start = checkpoint.step;
+    }
+
+    let collision_interval = collisions.output_interval.unwrap_or(data_rate);
+    let three_body_lost = sim.world.try_fetch::<ThreeBodyTracker>().map_or(0, |three_body| three_body.total_lost);
+    let box_indices = sim.world.try_fetch::<CollisionBoxes>().is_some();
+    let mut collision_stats = CollisionStatsOutput::new(run_dir, collisions.output_format, box_indices, three_body_lost)
+        .expect("Cannot create file.");
 
     // Run the simulation for a number of steps.
-    for _i in 0..sim_length {
+    for _i in start..sim_length {
         sim.step();
 
-        if (_i > 0) && (_i % 50_i32 == 0) {
-            let tracker = sim.world.read_resource::<CollisionsTracker>();
-            let _result = write_collisions_tracker(
-                &mut filename,
-                &_i,
-                &tracker.num_collisions,
-                &tracker.num_atoms,
-                &tracker.num_particles,
-            )
-            .expect("Could not write collision stats file.");
+        if let Some(interval) = config.output.checkpoint_interval {
+            if (_i + 1) % interval == 0 {
+                Checkpoint::capture(&sim.world, config, run_dir.name(), seed, &random_generator)
+                    .write(run_dir)
+                    .expect("Could not write checkpoint.");
+            }
         }
-    }
-    println!("Simulation completed in {} ms.", now.elapsed().as_millis());
-}
-
 
-// Write collision stats to file
-
-fn write_collisions_tracker(
-    filename: &mut File,
-    step: &i32,
-    num_collisions: &Vec<i32>,
-    num_atoms: &Vec<f64>,
-    num_particles: &Vec<i32>,
-) -> Result<(), Error> {
-    let str_collisions: Vec<String> = num_collisions.iter().map(|n| n.to_string()).collect();
-    let str_atoms: Vec<String> = num_atoms.iter().map(|n| format!("{:.2}", n)).collect();
-    let str_particles: Vec<String> = num_particles.iter().map(|n| n.to_string()).collect();
-    write!(
-        filename,
-        "{:?}\r\n{:}\r\n{:}\r\n{:}\r\n",
-        step,
-        str_collisions.join(" "),
-        str_atoms.join(" "),
-        str_particles.join(" ")
-    )?;
-    Ok(())
+        if (_i + 1) % collision_interval == 0 {
+            collision_stats
+                .write(
+                    _i + 1,
+                    (_i + 1) as f64 * dt,
+                    &sim.world.read_resource::<CollisionsTracker>(),
+                    sim.world.try_fetch::<CollisionBoxes>().as_deref(),
+                    sim.world.try_fetch::<ThreeBodyTracker>().as_deref(),
+                )
+                .expect("Could not write collision stats file.");
+        }
+    }
+    let wall_time = now.elapsed().as_millis();
+    manifest.complete(wall_time);
+    manifest.write(run_dir).expect("Could not write run manifest.");
+    println!("Simulation completed in {} ms.", wall_time);
+    diagnostics::print_summary(&sim.world.read_resource::<DiagnosticsHistory>());
 }
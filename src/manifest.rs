@@ -0,0 +1,102 @@
+//! Machine-readable record of how a run was produced.
+//!
+//! `manifest.json` is written into the run directory when the simulation starts, and rewritten
+//! with the wall-clock time and final list of output files once it completes. A run that
+//! crashed can be recognised by `completed: false`.
+
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::Error;
+use std::path::Path;
+
+use crate::config::ExperimentConfig;
+use crate::output::RunDirectory;
+use crate::seed::RunSeed;
+use crate::species::Species;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Serialize)]
+pub struct RunManifest<'a> {
+    pub run_name: String,
+    pub crate_version: &'static str,
+    /// `git describe` of the source tree the binary was built from, if it was built from a git checkout.
+    pub git_revision: Option<String>,
+    /// Local time at which the run started, RFC 3339.
+    pub started: String,
+    pub completed: bool,
+    /// Wall-clock duration of the simulation, in ms.
+    pub wall_time_ms: Option<u128>,
+    /// Seed given in the experiment file or on the command line, or drawn if neither was given.
+    pub base_seed: u64,
+    /// Index of the run within a batch; its seed is derived from `base_seed` and this index.
+    pub run_index: Option<u64>,
+    /// Seed of the random generator used to sample the initial cloud.
+    pub seed: u64,
+    /// Name of the run this one was resumed from, if it was resumed from a checkpoint.
+    pub resumed_from: Option<String>,
+    /// Step the run started from, nonzero for resumed runs.
+    pub start_step: u64,
+    pub atom_number: u64,
+    /// The species of the atoms, with the properties it was simulated with.
+    pub species: Species,
+    /// The resolved experiment configuration.
+    pub config: &'a ExperimentConfig,
+    /// Files written to the run directory, relative to it.
+    pub output_files: Vec<String>,
+}
+
+impl<'a> RunManifest<'a> {
+    pub fn new(config: &'a ExperimentConfig, species: &Species, run_dir: &RunDirectory, seed: RunSeed) -> Self {
+        RunManifest {
+            run_name: run_dir.name().to_string(),
+            crate_version: env!("CARGO_PKG_VERSION"),
+            git_revision: git_revision(),
+            started: chrono::Local::now().to_rfc3339(),
+            completed: false,
+            wall_time_ms: None,
+            base_seed: seed.base,
+            run_index: seed.run_index,
+            seed: seed.seed(),
+            resumed_from: None,
+            start_step: 0,
+            atom_number: config.atoms.number,
+            species: species.clone(),
+            config,
+            output_files: Vec::new(),
+        }
+    }
+
+    /// Marks the run as completed after `wall_time_ms`.
+    pub fn complete(&mut self, wall_time_ms: u128) {
+        self.completed = true;
+        self.wall_time_ms = Some(wall_time_ms);
+    }
+
+    /// Writes the manifest into the run directory, listing the files currently in it.
+    pub fn write(&mut self, run_dir: &RunDirectory) -> Result<(), Error> {
+        self.output_files = list_files(run_dir.path())?;
+        let file = File::create(run_dir.file(MANIFEST_FILE))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+fn list_files(dir: &Path) -> Result<Vec<String>, Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && name != MANIFEST_FILE {
+            files.push(name);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Revision of the git checkout the crate was built from, with a `-dirty` suffix for uncommitted
+/// changes, as recorded by `build.rs`.
+fn git_revision() -> Option<String> {
+    option_env!("GIT_REVISION").map(str::to_string)
+}
@@ -0,0 +1,105 @@
+//! Per-run output directories.
+//!
+//! Every simulation writes its files into its own directory, `<root>/<run name>`. The run name
+//! is taken from the experiment file, or generated from the start time when none is given, and
+//! runs of a batch append their index to it.
+//! An existing run directory is never written into unless overwriting is explicitly requested.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Format of tabular output files.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TableFormat {
+    /// Comma separated text with a header line.
+    #[default]
+    Csv,
+    /// Fixed-size little-endian records, for large tables.
+    Binary,
+}
+
+/// Format of the snapshots of atom positions, velocities and intensities.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotFormat {
+    /// `pos.txt`, `vel.txt` and `intensity.txt`, in the text format of atomecs' `FileOutputPlugin`.
+    #[default]
+    Text,
+    /// A single `atoms.npy` file, see `crate::snapshot`.
+    Npy,
+}
+
+/// Whether `name` is a single directory name, so a run directory of that name stays inside the
+/// output directory.
+pub fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    )
+}
+
+/// The directory holding all output files of a single run.
+pub struct RunDirectory {
+    name: String,
+    path: PathBuf,
+}
+
+impl RunDirectory {
+    /// Run name generated from the current local time, eg `run_20211104-153012`.
+    pub fn default_name() -> String {
+        format!("run_{}", chrono::Local::now().format("%Y%m%d-%H%M%S"))
+    }
+
+    /// Creates the directory `root/name` for a new run.
+    ///
+    /// Fails if the run directory already exists, unless `overwrite` is set, in which case the
+    /// previous contents are removed. The directory is created atomically, so two runs cannot
+    /// write into the same one.
+    pub fn create(root: &Path, name: &str, overwrite: bool) -> Result<Self, Error> {
+        let name = name.to_string();
+        let path = root.join(&name);
+
+        fs::create_dir_all(root)?;
+        if let Err(err) = fs::create_dir(&path) {
+            if err.kind() != ErrorKind::AlreadyExists {
+                return Err(err);
+            }
+            if !overwrite {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!(
+                        "output directory {} already exists, choose another run name or pass --overwrite",
+                        path.display()
+                    ),
+                ));
+            }
+            fs::remove_dir_all(&path)?;
+            fs::create_dir(&path)?;
+        }
+
+        Ok(RunDirectory { name, path })
+    }
+
+    /// Name of the run, which is also the name of its directory.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of the output file `file_name` inside the run directory.
+    pub fn file(&self, file_name: &str) -> PathBuf {
+        self.path.join(file_name)
+    }
+
+    /// As `file`, but as the `String` expected by `FileOutputPlugin`.
+    pub fn file_string(&self, file_name: &str) -> String {
+        self.file(file_name).to_string_lossy().into_owned()
+    }
+}
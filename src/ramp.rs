@@ -0,0 +1,282 @@
+//! Trajectories of the dipole trap beam parameters.
+//!
+//! The power, waist, focus position and ellipticity of each beam can be ramped independently.
+//! Each is described by a [RampProfile]: a sequence of segments, each starting from the value at
+//! which the previous one ended, optionally preceded by a delay during which the initial value is
+//! held. After the last segment the value is held.
+//!
+//! A [BeamRamp] is applied to a beam either by evaluating it in closed form every step, with an
+//! [AnalyticBeamRamp] component, or by turning it into `Ramp<GaussianBeam>` keyframes at a chosen
+//! resolution, between which atomecs interpolates linearly. Whenever the waist changes, the
+//! rayleigh range is recalculated for the beam's wavelength.
+
+use lib::dipole::DipoleLight;
+use lib::integrator::{Step, Timestep};
+use lib::laser::gaussian::{calculate_rayleigh_range, GaussianBeam};
+use lib::ramp::Ramp;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::ops::{Add, Mul, Sub};
+
+use crate::config::{invalid, non_negative, positive, ConfigError};
+
+/// A quantity that can be ramped: scalars such as the power, or vectors such as the focus position.
+pub trait RampValue: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self> {}
+
+impl<T> RampValue for T where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T> {}
+
+/// A single stage of a ramp. Times are relative to the start of the segment, and `x0` is the
+/// value at which the segment starts.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum RampSegment<T> {
+    /// Keeps the value constant.
+    Hold { duration: f64 },
+    /// Changes the value linearly to `to`.
+    Linear { duration: f64, to: T },
+    /// Decays exponentially towards `to`: `x(t) = to + (x0 - to) exp(-t / time_constant)`.
+    Exponential {
+        duration: f64,
+        to: T,
+        time_constant: f64,
+    },
+    /// The optimal ramp of O'Hara et al., PRA 64, 051403 (2001): `x(t) = x0 (1 + t / tau)^-beta`.
+    PowerLaw { duration: f64, tau: f64, beta: f64 },
+}
+
+impl<T: RampValue> RampSegment<T> {
+    pub fn duration(&self) -> f64 {
+        match self {
+            RampSegment::Hold { duration }
+            | RampSegment::Linear { duration, .. }
+            | RampSegment::Exponential { duration, .. }
+            | RampSegment::PowerLaw { duration, .. } => *duration,
+        }
+    }
+
+    /// Value a time `t` into the segment, for a segment starting at `x0`.
+    pub fn value_at(&self, x0: T, t: f64) -> T {
+        match *self {
+            RampSegment::Hold { .. } => x0,
+            RampSegment::Linear { duration, to } => x0 + (to - x0) * (t / duration),
+            RampSegment::Exponential { to, time_constant, .. } => {
+                to + (x0 - to) * (-t / time_constant).exp()
+            }
+            RampSegment::PowerLaw { tau, beta, .. } => x0 * (1.0 + t / tau).powf(-beta),
+        }
+    }
+
+    /// The value the segment ramps to, if it is given explicitly.
+    fn target(&self) -> Option<T> {
+        match *self {
+            RampSegment::Linear { to, .. } | RampSegment::Exponential { to, .. } => Some(to),
+            _ => None,
+        }
+    }
+
+    /// Whether the value changes linearly in time, so two keyframes describe the segment exactly.
+    fn is_linear(&self) -> bool {
+        matches!(self, RampSegment::Hold { .. } | RampSegment::Linear { .. })
+    }
+
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        positive(format!("{}.duration", key), self.duration())?;
+        match *self {
+            RampSegment::Exponential { time_constant, .. } => {
+                positive(format!("{}.time_constant", key), time_constant)?;
+            }
+            RampSegment::PowerLaw { tau, beta, .. } => {
+                positive(format!("{}.tau", key), tau)?;
+                non_negative(format!("{}.beta", key), beta)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// A piecewise ramp of a single quantity, built from consecutive segments.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RampProfile<T> {
+    /// Time before the first segment starts, in s.
+    #[serde(default)]
+    pub delay: f64,
+    pub segments: Vec<RampSegment<T>>,
+}
+
+impl<T: RampValue> RampProfile<T> {
+    /// Value at time `t` for a ramp starting at `initial`.
+    pub fn value_at(&self, initial: T, t: f64) -> T {
+        let mut x0 = initial;
+        let mut start = self.delay;
+        for segment in self.segments.iter() {
+            let end = start + segment.duration();
+            if t < end {
+                return segment.value_at(x0, (t - start).max(0.0));
+            }
+            x0 = segment.value_at(x0, segment.duration());
+            start = end;
+        }
+        x0
+    }
+
+    /// Times at which keyframes are needed to follow the ramp, spaced at most `resolution`
+    /// apart within curved segments.
+    fn keyframe_times(&self, resolution: f64) -> Vec<f64> {
+        let mut times = vec![self.delay];
+        let mut start = self.delay;
+        for segment in self.segments.iter() {
+            let duration = segment.duration();
+            let n = if segment.is_linear() {
+                1
+            } else {
+                (duration / resolution).ceil().max(1.0) as usize
+            };
+            times.extend((1..=n).map(|i| start + duration * i as f64 / n as f64));
+            start += duration;
+        }
+        times
+    }
+
+    /// Checks the timing of all segments, and the values they ramp to with `check_target`.
+    pub fn validate<F>(&self, key: &str, check_target: F) -> Result<(), ConfigError>
+    where
+        F: Fn(String, T) -> Result<(), ConfigError>,
+    {
+        non_negative(format!("{}.delay", key), self.delay)?;
+        if self.segments.is_empty() {
+            return Err(invalid(format!("{}.segments", key), "must contain at least one segment"));
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            let segment_key = format!("{}.segments[{}]", key, i);
+            segment.validate(&segment_key)?;
+            if let Some(target) = segment.target() {
+                check_target(format!("{}.to", segment_key), target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ramps of the parameters of a single beam. Parameters without a ramp keep their initial value.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BeamRamp {
+    /// Beam power, in W.
+    pub power: Option<RampProfile<f64>>,
+    /// 1/e^2 intensity radius at the focus, in m.
+    pub waist: Option<RampProfile<f64>>,
+    /// Position of the focus, in m.
+    pub focus: Option<RampProfile<Vector3<f64>>>,
+    pub ellipticity: Option<RampProfile<f64>>,
+}
+
+impl BeamRamp {
+    /// The beam at time `t`, for a beam equal to `initial` at t=0.
+    pub fn beam_at(&self, initial: &GaussianBeam, wavelength: f64, t: f64) -> GaussianBeam {
+        let mut beam = *initial;
+        if let Some(power) = &self.power {
+            beam.power = power.value_at(initial.power, t);
+        }
+        if let Some(waist) = &self.waist {
+            let initial_waist = initial.e_radius * 2.0_f64.sqrt();
+            beam.e_radius = waist.value_at(initial_waist, t) / 2.0_f64.sqrt();
+            beam.rayleigh_range = calculate_rayleigh_range(&wavelength, &beam.e_radius);
+        }
+        if let Some(focus) = &self.focus {
+            beam.intersection = focus.value_at(initial.intersection, t);
+        }
+        if let Some(ellipticity) = &self.ellipticity {
+            beam.ellipticity = ellipticity.value_at(initial.ellipticity, t);
+        }
+        beam
+    }
+
+    /// Keyframes for a beam equal to `initial` at t=0, spaced at most `resolution` apart within
+    /// curved segments and extending to `end_time` so the ramp covers the whole simulation.
+    ///
+    /// The rayleigh range is interpolated linearly between keyframes, so waist ramps need a
+    /// resolution fine enough for it to follow the waist.
+    pub fn keyframes(
+        &self,
+        initial: &GaussianBeam,
+        wavelength: f64,
+        resolution: f64,
+        end_time: f64,
+    ) -> Ramp<GaussianBeam> {
+        let mut times = vec![0.0, end_time];
+        if let Some(power) = &self.power {
+            times.extend(power.keyframe_times(resolution));
+        }
+        if let Some(waist) = &self.waist {
+            times.extend(waist.keyframe_times(resolution));
+        }
+        if let Some(focus) = &self.focus {
+            times.extend(focus.keyframe_times(resolution));
+        }
+        if let Some(ellipticity) = &self.ellipticity {
+            times.extend(ellipticity.keyframe_times(resolution));
+        }
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        times.dedup_by(|a, b| (*a - *b).abs() < 1e-12);
+
+        Ramp {
+            prev: 0,
+            keyframes: times
+                .into_iter()
+                .map(|t| (t, self.beam_at(initial, wavelength, t)))
+                .collect(),
+        }
+    }
+
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        if let Some(power) = &self.power {
+            power.validate(&format!("{}.power", key), non_negative)?;
+        }
+        if let Some(waist) = &self.waist {
+            waist.validate(&format!("{}.waist", key), positive)?;
+        }
+        if let Some(focus) = &self.focus {
+            focus.validate(&format!("{}.focus", key), |_, _| Ok(()))?;
+        }
+        if let Some(ellipticity) = &self.ellipticity {
+            ellipticity.validate(&format!("{}.ellipticity", key), non_negative)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets the parameters of a [GaussianBeam] from a [BeamRamp] evaluated at the current time.
+///
+/// Unlike `Ramp<GaussianBeam>`, no keyframes are stored, so memory does not grow with the length of the ramp.
+pub struct AnalyticBeamRamp {
+    /// The beam at t=0.
+    pub initial: GaussianBeam,
+    pub ramp: BeamRamp,
+}
+
+impl Component for AnalyticBeamRamp {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Updates every beam with an [AnalyticBeamRamp].
+pub struct AnalyticBeamRampSystem;
+
+impl<'a> System<'a> for AnalyticBeamRampSystem {
+    type SystemData = (
+        WriteStorage<'a, GaussianBeam>,
+        ReadStorage<'a, AnalyticBeamRamp>,
+        ReadStorage<'a, DipoleLight>,
+        ReadExpect<'a, Timestep>,
+        ReadExpect<'a, Step>,
+    );
+
+    fn run(&mut self, (mut beams, ramps, lights, timestep, step): Self::SystemData) {
+        let t = step.n as f64 * timestep.delta;
+        for (beam, ramp, light) in (&mut beams, &ramps, &lights).join() {
+            *beam = ramp.ramp.beam_at(&ramp.initial, light.wavelength, t);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Seeding of the random generators used to sample the initial cloud and the collisions.
+//!
+//! Each run is governed by a single 64-bit seed, recorded in the run manifest, so that any run
+//! can be reproduced exactly. Runs of a batch share a base seed and derive their own seed from
+//! it and their index in the batch.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Seeds used by a run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct RunSeed {
+    /// Seed given by the user, or drawn from system entropy if none was given.
+    pub base: u64,
+    /// Index of the run within a batch, if the run is part of one.
+    pub run_index: Option<u64>,
+}
+
+impl RunSeed {
+    /// Uses `base` if given, otherwise draws a fresh seed from system entropy.
+    pub fn resolve(base: Option<u64>, run_index: Option<u64>) -> Self {
+        RunSeed {
+            base: base.unwrap_or_else(rand::random),
+            run_index,
+        }
+    }
+
+    /// The seed the random generator of this run is created from.
+    pub fn seed(&self) -> u64 {
+        match self.run_index {
+            Some(index) => derive_seed(self.base, index),
+            None => self.base,
+        }
+    }
+
+    pub fn rng(&self) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.seed())
+    }
+
+    /// A generator independent of [RunSeed::rng], for the collisions.
+    pub fn collision_rng(&self) -> ChaCha8Rng {
+        let mut rng = self.rng();
+        rng.set_stream(1);
+        rng
+    }
+}
+
+/// Derives the seed of run `index` of a batch from the batch's `base` seed.
+///
+/// Uses the SplitMix64 finaliser, so neighbouring indices give uncorrelated seeds.
+pub fn derive_seed(base: u64, index: u64) -> u64 {
+    let mut z = base.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
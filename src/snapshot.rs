@@ -0,0 +1,93 @@
+//! Snapshots of the atoms in NumPy's `.npy` format.
+//!
+//! `atoms.npy` is a one-dimensional array of structured records, one per atom per snapshot, with
+//! the fields `step` (`<u8`), `generation` (`<i4`) and `id` (`<u4`) of the atom's entity, `pos` and
+//! `vel` (three `<f8` each, in SI units) and `intensity` (one `<f8` per beam, in W/m^2). It can be
+//! memory-mapped with `numpy.load("atoms.npy", mmap_mode="r")`, and a single atom followed by
+//! selecting on `generation` and `id`.
+//!
+//! The header is rewritten after every snapshot, so the file is valid while the run is in progress.
+
+use lib::atom::{Atom, Position, Velocity};
+use lib::integrator::Step;
+use lib::laser::intensity::LaserIntensitySamplers;
+use specs::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Error, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+/// Length of the header dictionary, padded so the data starts 64-byte aligned whatever the
+/// number of records.
+const HEADER_LENGTH: usize = 502;
+
+/// Writes a snapshot of every atom to an `.npy` file every `interval` steps, for `N` beams.
+pub struct NpySnapshotSystem<const N: usize> {
+    writer: BufWriter<File>,
+    interval: u64,
+    records: u64,
+}
+
+impl<const N: usize> NpySnapshotSystem<N> {
+    pub fn new(path: &Path, interval: u64) -> Result<Self, Error> {
+        let mut system = NpySnapshotSystem {
+            writer: BufWriter::new(File::create(path)?),
+            interval,
+            records: 0,
+        };
+        system.write_header()?;
+        Ok(system)
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        let header = format!(
+            "{{'descr': [('step', '<u8'), ('generation', '<i4'), ('id', '<u4'), ('pos', '<f8', (3,)), ('vel', '<f8', (3,)), ('intensity', '<f8', ({},))], 'fortran_order': False, 'shape': ({},), }}",
+            N, self.records
+        );
+        let header = format!("{:width$}\n", header, width = HEADER_LENGTH - 1);
+        self.writer.write_all(MAGIC)?;
+        self.writer.write_all(&(HEADER_LENGTH as u16).to_le_bytes())?;
+        self.writer.write_all(header.as_bytes())
+    }
+
+    /// Updates the record count in the header, leaving the file positioned at its end.
+    fn update_header(&mut self) -> Result<(), Error> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+impl<'a, const N: usize> System<'a> for NpySnapshotSystem<N> {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Atom>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, LaserIntensitySamplers<N>>,
+        ReadExpect<'a, Step>,
+    );
+
+    fn run(&mut self, (entities, atoms, positions, velocities, samplers, step): Self::SystemData) {
+        if step.n % self.interval != 0 {
+            return;
+        }
+        for (entity, _, pos, vel, samplers) in (&entities, &atoms, &positions, &velocities, samplers.maybe()).join() {
+            let mut record = Vec::with_capacity(16 + 8 * (6 + N));
+            record.extend_from_slice(&step.n.to_le_bytes());
+            record.extend_from_slice(&entity.gen().id().to_le_bytes());
+            record.extend_from_slice(&entity.id().to_le_bytes());
+            for x in pos.pos.iter().chain(vel.vel.iter()) {
+                record.extend_from_slice(&x.to_le_bytes());
+            }
+            for i in 0..N {
+                let intensity = samplers.map_or(f64::NAN, |samplers| samplers.contents[i].intensity);
+                record.extend_from_slice(&intensity.to_le_bytes());
+            }
+            self.writer.write_all(&record).expect("Could not write snapshot file.");
+            self.records += 1;
+        }
+        self.update_header().expect("Could not write snapshot file.");
+    }
+}
@@ -0,0 +1,245 @@
+//! Properties of the atomic species that can be simulated.
+//!
+//! Selecting a species with `atoms.species` sets the mass, the transitions that determine the
+//! polarizability and the s-wave scattering length from the table in [Species::known], so the
+//! components of the atoms and the `CollisionParameters` always describe the same atom.
+//!
+//! The polarizability is summed over the strong transitions from the ground state. A transition of
+//! linewidth `G` at angular frequency `w0` contributes
+//! `U = -(g' / 3g) (3 pi c^2 / 2 w0^3) G (1 / (w0 - w) + 1 / (w0 + w)) I` to the potential of light of
+//! frequency `w`, where `g` and `g'` are the degeneracies of the ground and excited levels, so that
+//! eg the D2 and D1 lines of the alkalis carry 2/3 and 1/3 of the strength. The second, counter-
+//! rotating term can be neglected for comparison with rotating-wave calculations. The core and the
+//! weaker transitions are included as a constant background, chosen so the static polarizability
+//! matches its measured or calculated value: 197.1 a.u. for Sr, 318.8 for Rb, 401.0 for Cs and
+//! 164.1 for Li.
+//!
+//! The collisional cross section is `8 pi a^2` for identical bosons. Identical fermions do not
+//! collide in the s-wave, so fermions are taken to be in a mixture of spin states and collide with
+//! the cross section `4 pi a^2` of distinguishable atoms.
+
+use lib::constant::{C, PI};
+use lib::dipole::Polarizability;
+use serde::{Deserialize, Serialize};
+
+/// Bohr radius, in m.
+pub const BOHR_RADIUS: f64 = 5.291_772_109e-11;
+/// Atomic unit of polarizability, in C^2 m^2 J^-1.
+pub const ATOMIC_UNIT_POLARIZABILITY: f64 = 1.648_777_274e-41;
+/// Vacuum permittivity, in F/m.
+const EPSILON_0: f64 = 8.854_187_813e-12;
+
+/// Quantum statistics of the atoms.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Statistics {
+    #[default]
+    Boson,
+    Fermion,
+}
+
+/// An optical transition from the ground state.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    /// In m.
+    pub wavelength: f64,
+    /// Natural linewidth of the excited level, in s^-1.
+    pub linewidth: f64,
+    /// Degeneracy `2J + 1` of the ground level, 1 if omitted.
+    #[serde(default = "default_ground_degeneracy")]
+    pub ground_degeneracy: u32,
+    /// Degeneracy `2J' + 1` of the excited level, 3 if omitted.
+    #[serde(default = "default_excited_degeneracy")]
+    pub excited_degeneracy: u32,
+}
+
+fn default_ground_degeneracy() -> u32 {
+    1
+}
+
+fn default_excited_degeneracy() -> u32 {
+    3
+}
+
+impl Transition {
+    /// A `J = 0` to `J' = 1` transition, such as the intercombination and principal lines of the
+    /// alkaline earths.
+    fn singlet(wavelength: f64, linewidth: f64) -> Self {
+        Transition {
+            wavelength,
+            linewidth,
+            ground_degeneracy: 1,
+            excited_degeneracy: 3,
+        }
+    }
+
+    /// A transition from the `J = 1/2` ground level of an alkali to an excited level of
+    /// degeneracy `excited_degeneracy`.
+    fn alkali(wavelength: f64, linewidth: f64, excited_degeneracy: u32) -> Self {
+        Transition {
+            wavelength,
+            linewidth,
+            ground_degeneracy: 2,
+            excited_degeneracy,
+        }
+    }
+
+    /// Contribution to the polarizability prefactor in light of angular frequency `omega`.
+    fn prefactor(&self, omega: f64, counter_rotating: bool) -> f64 {
+        let omega_0 = 2.0 * PI * C / self.wavelength;
+        let weight = self.excited_degeneracy as f64 / (3.0 * self.ground_degeneracy as f64);
+        let detuning = 1.0 / (omega_0 - omega) + if counter_rotating { 1.0 / (omega_0 + omega) } else { 0.0 };
+        weight * 3.0 * PI * C * C / (2.0 * omega_0.powi(3)) * self.linewidth * detuning
+    }
+}
+
+/// An atomic species.
+#[derive(Serialize, Clone, Debug)]
+pub struct Species {
+    /// Name of the species, eg `88Sr`, or `custom` for one given by its properties.
+    pub name: String,
+    /// In amu.
+    pub mass: f64,
+    /// Transitions contributing to the polarizability.
+    pub transitions: Vec<Transition>,
+    /// Static polarizability of the core and the transitions not listed, in atomic units.
+    pub background_polarizability: f64,
+    /// s-wave scattering length, in units of the Bohr radius. Unknown for custom species that give
+    /// their cross section directly.
+    pub scattering_length: Option<f64>,
+    pub statistics: Statistics,
+    /// Elastic collisional cross section, in m^2.
+    pub cross_section: f64,
+    /// Three-body loss coefficient `L3` of a thermal cloud, in m^6/s, if known.
+    pub three_body_loss: Option<f64>,
+}
+
+impl Species {
+    /// Names of the species in [Species::known].
+    pub const NAMES: [&'static str; 6] = ["88Sr", "87Sr", "84Sr", "87Rb", "133Cs", "6Li"];
+
+    /// Looks up a species by its name, eg `88Sr`.
+    ///
+    /// The scattering lengths of 133Cs and 6Li depend strongly on the magnetic field. They are
+    /// given at 21 G for 133Cs, and at 300 G for a 6Li mixture of the two lowest hyperfine states,
+    /// fields at which these species are commonly evaporated. Only 87Rb has a three-body loss
+    /// coefficient, measured in a thermal cloud in the `F = 1, mF = -1` state.
+    pub fn known(name: &str) -> Option<Self> {
+        let strontium = vec![
+            Transition::singlet(460.862e-9, 1.90e8),
+            Transition::singlet(689.449e-9, 4.69e4),
+        ];
+        let (mass, transitions, background_polarizability, scattering_length, statistics, three_body_loss) = match name {
+            "88Sr" => (87.905_612, strontium, 11.2, -1.4, Statistics::Boson, None),
+            "87Sr" => (86.908_877, strontium, 11.2, 96.2, Statistics::Fermion, None),
+            "84Sr" => (83.913_425, strontium, 11.2, 122.7, Statistics::Boson, None),
+            "87Rb" => (
+                86.909_180,
+                vec![
+                    Transition::alkali(780.241e-9, 3.812e7, 4),
+                    Transition::alkali(794.979e-9, 3.614e7, 2),
+                ],
+                10.5,
+                100.4,
+                Statistics::Boson,
+                Some(4.3e-41),
+            ),
+            "133Cs" => (
+                132.905_452,
+                vec![
+                    Transition::alkali(852.347e-9, 3.288e7, 4),
+                    Transition::alkali(894.593e-9, 2.871e7, 2),
+                ],
+                17.6,
+                210.0,
+                Statistics::Boson,
+                None,
+            ),
+            "6Li" => (
+                6.015_123,
+                vec![
+                    Transition::alkali(670.977e-9, 3.689e7, 4),
+                    Transition::alkali(670.992e-9, 3.689e7, 2),
+                ],
+                2.1,
+                -290.0,
+                Statistics::Fermion,
+                None,
+            ),
+            _ => return None,
+        };
+        Some(Species {
+            name: name.to_string(),
+            mass,
+            transitions,
+            background_polarizability,
+            scattering_length: None,
+            statistics,
+            cross_section: 0.0,
+            three_body_loss,
+        }
+        .with_scattering_length(scattering_length))
+    }
+
+    /// Sets the scattering length (in Bohr radii) and the cross section that follows from it.
+    pub fn with_scattering_length(mut self, scattering_length: f64) -> Self {
+        let a = scattering_length * BOHR_RADIUS;
+        self.scattering_length = Some(scattering_length);
+        self.cross_section = match self.statistics {
+            Statistics::Boson => 8.0 * PI * a * a,
+            Statistics::Fermion => 4.0 * PI * a * a,
+        };
+        self
+    }
+
+    /// Polarizability of the atoms in light of `wavelength` (in m), summed over their transitions,
+    /// with or without the counter-rotating terms.
+    pub fn polarizability(&self, wavelength: f64, counter_rotating: bool) -> Polarizability {
+        let omega = 2.0 * PI * C / wavelength;
+        let background = self.background_polarizability * ATOMIC_UNIT_POLARIZABILITY / (2.0 * EPSILON_0 * C);
+        Polarizability {
+            prefactor: background
+                + self
+                    .transitions
+                    .iter()
+                    .map(|transition| transition.prefactor(omega, counter_rotating))
+                    .sum::<f64>(),
+        }
+    }
+}
+
+/// Polarizability with the given prefactor, in atomic units.
+pub fn in_atomic_units(polarizability: &Polarizability) -> f64 {
+    polarizability.prefactor * 2.0 * EPSILON_0 * C / ATOMIC_UNIT_POLARIZABILITY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polarizability(name: &str, wavelength: f64) -> f64 {
+        let species = Species::known(name).unwrap();
+        in_atomic_units(&species.polarizability(wavelength, true))
+    }
+
+    #[test]
+    fn polarizability_at_1064_nm() {
+        assert!((polarizability("87Rb", 1064e-9) - 687.0).abs() < 5.0);
+        assert!((polarizability("88Sr", 1064e-9) - 240.0).abs() < 5.0);
+    }
+
+    #[test]
+    fn static_polarizability_matches_background_anchors() {
+        for (name, expected) in [("88Sr", 197.1), ("87Rb", 318.8), ("133Cs", 401.0), ("6Li", 164.1)] {
+            let static_limit = polarizability(name, 1.0);
+            assert!(
+                (static_limit - expected).abs() < 1.0,
+                "{} has a static polarizability of {:.1} a.u., expected {}",
+                name,
+                static_limit,
+                expected
+            );
+        }
+    }
+}
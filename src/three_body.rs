@@ -0,0 +1,83 @@
+//! Three-body recombination loss.
+//!
+//! Three atoms that meet can recombine into a molecule and a third body, which leave the trap.
+//! An atom in a gas of density `n` is lost at the rate `L3 n^2`. The density is taken to be uniform
+//! within each collision box of volume `V`, and only the other particles of the box can recombine
+//! with a particle, so in a box of `N` particles of `W` atoms each, each particle is lost at the
+//! rate `L3 W^2 (N - 1)(N - 2) / V^2` and removed with probability `1 - exp(-rate dt)` every step.
+//! Like the pairs `N (N - 1)` of the collisions, this keeps a particle from recombining with itself,
+//! which would otherwise overestimate the loss of sparsely occupied boxes.
+//!
+//! The losses are written with the collision statistics, see `crate::collision_stats`.
+
+use lib::atom::{Atom, Position};
+use lib::collisions::CollisionParameters;
+use lib::destructor::ToBeDestroyed;
+use lib::integrator::Timestep;
+use rand::Rng;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::collisions::{box_index, CollisionRng};
+
+/// Atoms removed by three-body loss.
+#[derive(Default)]
+pub struct ThreeBodyTracker {
+    /// Particles removed in the last step.
+    pub lost: u64,
+    /// Particles removed since the start of the run.
+    pub total_lost: u64,
+    /// Largest loss rate of a particle in the collision boxes in the last step, in s^-1.
+    pub peak_rate: f64,
+}
+
+/// Marks atoms lost to three-body recombination [ToBeDestroyed].
+pub struct ThreeBodyLossSystem {
+    /// Loss coefficient `L3`, in m^6/s.
+    pub coefficient: f64,
+}
+
+impl<'a> System<'a> for ThreeBodyLossSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Atom>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, ToBeDestroyed>,
+        ReadExpect<'a, Timestep>,
+        ReadExpect<'a, CollisionParameters>,
+        WriteExpect<'a, ThreeBodyTracker>,
+        WriteExpect<'a, CollisionRng>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, atoms, positions, mut to_be_destroyed, timestep, params, mut tracker, mut rng): Self::SystemData,
+    ) {
+        let mut boxes: BTreeMap<i64, Vec<Entity>> = BTreeMap::new();
+        for (entity, _, position, _) in (&entities, &atoms, &positions, !&to_be_destroyed).join() {
+            if let Some(index) = box_index(&position.pos, params.box_number, params.box_width) {
+                boxes.entry(index).or_default().push(entity);
+            }
+        }
+
+        let volume = params.box_width.powi(3);
+        let mut lost = Vec::new();
+        tracker.peak_rate = 0.0;
+        for particles in boxes.values() {
+            let n = particles.len() as f64;
+            let rate = self.coefficient * params.macroparticle.powi(2) * (n - 1.0) * (n - 2.0)
+                / volume.powi(2);
+            tracker.peak_rate = tracker.peak_rate.max(rate);
+            let probability = -(-rate * timestep.delta).exp_m1();
+            lost.extend(particles.iter().filter(|_| rng.0.gen::<f64>() < probability));
+        }
+
+        tracker.lost = lost.len() as u64;
+        tracker.total_lost += tracker.lost;
+        for entity in lost {
+            to_be_destroyed
+                .insert(entity, ToBeDestroyed)
+                .expect("Could not mark atom lost to three-body recombination.");
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Analytic properties of the crossed gaussian beam dipole trap.
+//!
+//! The dipole potential is `U = -prefactor * I`, with the prefactor of `lib::dipole::Polarizability`.
+//! Depths and frequencies are calculated for beams whose foci coincide, using the harmonic
+//! expansion of each beam's intensity about its focus,
+//! `I = I0 (1 - rho^2 / e_radius^2 - z^2 / rayleigh_range^2)`. Ellipticity is neglected.
+//!
+//! Gravity adds `-m g.r` to the potential, which sags the minimum below the beam centre and tilts
+//! the trap. With gravity the minimum and its curvature are found numerically, and the escape
+//! energy is the lowest barrier along straight paths from the minimum. These paths slightly
+//! overestimate the barrier of the curved path through the true saddle point.
+
+use lib::constant::{AMU, BOLTZCONST, PI};
+use lib::laser::gaussian::GaussianBeam;
+use nalgebra::{Matrix3, Unit, Vector3};
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::config::ExperimentConfig;
+
+/// Intensity of `beam` at `pos`, in W/m^2.
+pub fn beam_intensity(beam: &GaussianBeam, pos: &Vector3<f64>) -> f64 {
+    let r = pos - beam.intersection;
+    let z = r.dot(&beam.direction);
+    let rho_squared = (r - z * beam.direction).norm_squared();
+    let e_radius_squared = beam.e_radius.powi(2) * (1.0 + (z / beam.rayleigh_range).powi(2));
+    beam.power / (PI * e_radius_squared) * (-rho_squared / e_radius_squared).exp()
+}
+
+/// Number of directions searched for the lowest barrier of a trap tilted by gravity.
+const ESCAPE_DIRECTIONS: usize = 200;
+/// Points sampled along each direction.
+const ESCAPE_SAMPLES: usize = 64;
+/// Distance out to which the barrier is searched, in units of the largest beam waist.
+const ESCAPE_DISTANCE: f64 = 5.0;
+
+/// Depth and harmonic frequencies of a crossed beam trap.
+pub struct TrapProperties {
+    pub beams: Vec<GaussianBeam>,
+    /// Polarizability prefactor of the trapped atoms, see `lib::dipole::Polarizability`.
+    pub prefactor: f64,
+    /// Gravitational acceleration, in m/s^2.
+    pub gravity: Vector3<f64>,
+    /// Centre of the trap, taken as the mean of the beam foci, in m.
+    pub centre: Vector3<f64>,
+    /// Position of the potential minimum, in m. It sags below the centre under gravity.
+    pub minimum: Vector3<f64>,
+    /// Depth of the dipole potential at the trap centre, in J.
+    pub central_depth: f64,
+    /// Potential at the lowest point through which atoms escape, in J. Without gravity, atoms
+    /// escape along the axis of one of the beams, where they remain confined by that beam only.
+    pub escape_energy: f64,
+    /// Energy needed to escape the trap from its minimum, in J.
+    pub depth: f64,
+    /// Angular trap frequencies along the principal axes, in ascending order, in rad/s.
+    pub frequencies: Vector3<f64>,
+    /// Curvature of the potential at its minimum, in J/m^2.
+    pub curvature: Matrix3<f64>,
+    /// Atomic mass, in kg.
+    pub mass: f64,
+}
+
+impl TrapProperties {
+    /// Calculates the trap formed by `beams` for atoms of `mass` (in amu) with polarizability
+    /// `prefactor`, under the gravitational acceleration `gravity`.
+    pub fn calculate(beams: &[GaussianBeam], prefactor: f64, mass: f64, gravity: Vector3<f64>) -> Self {
+        let mass = mass * AMU;
+        let peak_depths: Vec<f64> = beams
+            .iter()
+            .map(|beam| prefactor * beam.power / (PI * beam.e_radius.powi(2)))
+            .collect();
+        let central_depth: f64 = peak_depths.iter().sum();
+        let depth = if beams.len() > 1 {
+            peak_depths
+                .iter()
+                .map(|d| central_depth - d)
+                .fold(f64::INFINITY, f64::min)
+        } else {
+            central_depth
+        };
+
+        let mut curvature = Matrix3::zeros();
+        for (beam, u0) in beams.iter().zip(peak_depths.iter()) {
+            let axial = beam.direction * beam.direction.transpose();
+            let radial = Matrix3::identity() - axial;
+            curvature += 2.0 * u0 * (radial / beam.e_radius.powi(2) + axial / beam.rayleigh_range.powi(2));
+        }
+        let centre = beams.iter().map(|beam| beam.intersection).sum::<Vector3<f64>>() / beams.len().max(1) as f64;
+
+        let mut trap = TrapProperties {
+            beams: beams.to_vec(),
+            prefactor,
+            gravity,
+            centre,
+            minimum: centre,
+            central_depth,
+            escape_energy: 0.0,
+            depth,
+            frequencies: Vector3::zeros(),
+            curvature,
+            mass,
+        };
+        if gravity == Vector3::zeros() {
+            trap.escape_energy = trap.potential(&centre) + depth;
+        } else {
+            trap.minimum = trap.find_minimum();
+            trap.curvature = trap.hessian(&trap.minimum);
+            trap.escape_energy = trap.lowest_barrier();
+            trap.depth = (trap.escape_energy - trap.potential(&trap.minimum)).max(0.0);
+        }
+        let mut frequencies = trap
+            .curvature
+            .symmetric_eigenvalues()
+            .map(|k| (k.max(0.0) / mass).sqrt());
+        frequencies.as_mut_slice().sort_by(|a, b| a.partial_cmp(b).unwrap());
+        trap.frequencies = frequencies;
+        trap
+    }
+
+    /// Whether the trap was calculated for `beams`, so it need not be calculated again.
+    pub fn has_beams(&self, beams: &[GaussianBeam]) -> bool {
+        self.beams.len() == beams.len()
+            && self.beams.iter().zip(beams).all(|(a, b)| {
+                a.intersection == b.intersection
+                    && a.direction == b.direction
+                    && a.e_radius == b.e_radius
+                    && a.power == b.power
+                    && a.rayleigh_range == b.rayleigh_range
+            })
+    }
+
+    /// Potential of all beams and gravity at `pos`, in J. The gravitational potential is zero at
+    /// the trap centre.
+    pub fn potential(&self, pos: &Vector3<f64>) -> f64 {
+        -self.prefactor * self.beams.iter().map(|beam| beam_intensity(beam, pos)).sum::<f64>()
+            - self.mass * self.gravity.dot(&(pos - self.centre))
+    }
+
+    /// Displacement of the potential minimum from the trap centre, in m.
+    pub fn sag(&self) -> Vector3<f64> {
+        self.minimum - self.centre
+    }
+
+    /// Step for numerical derivatives of the potential, in m.
+    fn derivative_step(&self) -> f64 {
+        1e-4 * self.beams.iter().map(|beam| beam.e_radius).fold(f64::INFINITY, f64::min)
+    }
+
+    fn gradient(&self, pos: &Vector3<f64>) -> Vector3<f64> {
+        let h = self.derivative_step();
+        Vector3::from_fn(|i, _| {
+            let dx = Vector3::ith(i, h);
+            (self.potential(&(pos + dx)) - self.potential(&(pos - dx))) / (2.0 * h)
+        })
+    }
+
+    fn hessian(&self, pos: &Vector3<f64>) -> Matrix3<f64> {
+        let h = self.derivative_step();
+        Matrix3::from_fn(|i, j| {
+            let dx = Vector3::ith(i, h);
+            let dy = Vector3::ith(j, h);
+            (self.potential(&(pos + dx + dy)) - self.potential(&(pos + dx - dy)) - self.potential(&(pos - dx + dy))
+                + self.potential(&(pos - dx - dy)))
+                / (4.0 * h * h)
+        })
+    }
+
+    /// Finds the minimum of the potential by Newton's method, starting from the harmonic estimate
+    /// of the sag. Returns the centre if the potential has no minimum near it.
+    fn find_minimum(&self) -> Vector3<f64> {
+        let max_step = self.beams.iter().map(|beam| beam.e_radius).fold(f64::INFINITY, f64::min) / 4.0;
+        let mut pos = match self.curvature.try_inverse() {
+            Some(inverse) => self.centre + inverse * (self.mass * self.gravity),
+            None => self.centre,
+        };
+        for _ in 0..100 {
+            let hessian = self.hessian(&pos);
+            let step = match hessian.cholesky() {
+                Some(cholesky) => cholesky.solve(&self.gradient(&pos)),
+                None => return self.centre,
+            };
+            let step = if step.norm() > max_step { step * (max_step / step.norm()) } else { step };
+            pos -= step;
+            if step.norm() < 1e-3 * self.derivative_step() {
+                break;
+            }
+        }
+        pos
+    }
+
+    /// The lowest of the highest potentials met along straight paths leaving the minimum.
+    ///
+    /// The directions of a Fibonacci lattice and the axes of the beams are searched, and the best
+    /// of them refined by a pattern search over the sphere.
+    fn lowest_barrier(&self) -> f64 {
+        let golden_angle = PI * (3.0 - 5.0_f64.sqrt());
+        let lattice = (0..ESCAPE_DIRECTIONS).map(|i| {
+            let z = 1.0 - 2.0 * (i as f64 + 0.5) / ESCAPE_DIRECTIONS as f64;
+            let rho = (1.0 - z * z).sqrt();
+            let phi = golden_angle * i as f64;
+            Vector3::new(rho * phi.cos(), rho * phi.sin(), z)
+        });
+        let axes = self.beams.iter().flat_map(|beam| [beam.direction, -beam.direction]);
+        let (mut direction, mut barrier) = lattice
+            .chain(axes)
+            .map(|direction| {
+                let direction = Unit::new_normalize(direction);
+                (direction, self.barrier_along(&direction))
+            })
+            .fold((Vector3::z_axis(), f64::INFINITY), |best, candidate| {
+                if candidate.1 < best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            });
+
+        let mut angle = (4.0 / ESCAPE_DIRECTIONS as f64).sqrt();
+        while angle > 1e-4 {
+            let reference = if direction.x.abs() < 0.9 { Vector3::x() } else { Vector3::y() };
+            let first = Unit::new_normalize(direction.cross(&reference));
+            let second = direction.cross(&first);
+            let improvement = [first.into_inner(), -first.into_inner(), second, -second]
+                .iter()
+                .map(|offset| {
+                    let candidate = Unit::new_normalize(direction.as_ref() + offset * angle);
+                    (candidate, self.barrier_along(&candidate))
+                })
+                .find(|(_, candidate)| *candidate < barrier);
+            match improvement {
+                Some((better, better_barrier)) => {
+                    direction = better;
+                    barrier = better_barrier;
+                }
+                None => angle /= 2.0,
+            }
+        }
+        barrier
+    }
+
+    /// The highest potential met along the straight path from the minimum along `direction`.
+    fn barrier_along(&self, direction: &Unit<Vector3<f64>>) -> f64 {
+        let distance = ESCAPE_DISTANCE * self.beams.iter().map(|beam| beam.e_radius * 2.0_f64.sqrt()).fold(0.0, f64::max);
+        (1..=ESCAPE_SAMPLES)
+            .map(|j| {
+                let pos = self.minimum + direction.as_ref() * (distance * j as f64 / ESCAPE_SAMPLES as f64);
+                self.potential(&pos)
+            })
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    /// Angular trap frequency along the unit vector `axis`, in rad/s.
+    pub fn frequency_along(&self, axis: &Vector3<f64>) -> f64 {
+        ((axis.transpose() * self.curvature * axis)[0].max(0.0) / self.mass).sqrt()
+    }
+
+    /// Geometric mean of the angular trap frequencies, in rad/s.
+    pub fn mean_frequency(&self) -> f64 {
+        self.frequencies.iter().product::<f64>().cbrt()
+    }
+
+    /// Effective volume `(2 pi kB T / (m w^2))^(3/2)` of a thermal cloud at `temperature` (in K)
+    /// in the harmonic approximation, in m^3. The peak density is the atom number divided by it.
+    pub fn harmonic_volume(&self, temperature: f64) -> f64 {
+        (2.0 * PI * BOLTZCONST * temperature / (self.mass * self.mean_frequency().powi(2))).powf(1.5)
+    }
+}
+
+/// Converts an energy in J to a temperature in uK.
+pub fn to_microkelvin(energy: f64) -> f64 {
+    energy / BOLTZCONST * 1e6
+}
+
+/// Prints the depth and frequencies of the trap, and its sag under gravity.
+pub fn print_summary(trap: &TrapProperties) {
+    let f = trap.frequencies / (2.0 * PI);
+    println!(
+        "Initial trap depth {:.1} uK, trap frequencies {:.1}, {:.1}, {:.1} Hz",
+        to_microkelvin(trap.depth),
+        f[0],
+        f[1],
+        f[2]
+    );
+    if trap.gravity != Vector3::zeros() {
+        println!("  gravitational sag {:.2} um", trap.sag().norm() * 1e6);
+    }
+}
+
+/// Writes the trap properties at every `interval` steps of the beam ramps to a csv file, for atoms
+/// of `mass` (in amu).
+///
+/// The harmonic volume is given for a cloud at 1 uK; it scales as `T^(3/2)`. The sag is the
+/// distance of the potential minimum below the trap centre.
+pub fn write_trap_table(
+    path: &Path,
+    config: &ExperimentConfig,
+    prefactor: f64,
+    mass: f64,
+    interval: u64,
+) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "step,time,depth_uK,central_depth_uK,frequency_1_Hz,frequency_2_Hz,frequency_3_Hz,mean_frequency_Hz,harmonic_volume_1uK_m3,sag_um")?;
+    let gravity = config.gravity();
+    let initial: Vec<GaussianBeam> = config.beams.iter().map(crate::beams::gaussian_beam).collect();
+    for step in (0..=config.simulation.steps).step_by(interval as usize) {
+        let t = step as f64 * config.simulation.timestep;
+        let beams: Vec<GaussianBeam> = config
+            .beams
+            .iter()
+            .zip(initial.iter())
+            .map(|(beam, gaussian_beam)| match &beam.ramp {
+                Some(ramp) => ramp.beam_at(gaussian_beam, beam.wavelength, t),
+                None => *gaussian_beam,
+            })
+            .collect();
+        let trap = TrapProperties::calculate(&beams, prefactor, mass, gravity);
+        let f = trap.frequencies / (2.0 * PI);
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{}",
+            step,
+            t,
+            to_microkelvin(trap.depth),
+            to_microkelvin(trap.central_depth),
+            f[0],
+            f[1],
+            f[2],
+            trap.mean_frequency() / (2.0 * PI),
+            trap.harmonic_volume(1e-6),
+            trap.sag().norm() * 1e6
+        )?;
+    }
+    writer.flush()
+}
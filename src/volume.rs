@@ -0,0 +1,172 @@
+//! The simulation volume, outside which atoms are deleted.
+//!
+//! The volume is either a sphere centred on the origin, whose radius can be ramped like the beam
+//! parameters, or the union of a cylinder around each beam, which follows the waist and focus of
+//! its beam as they are ramped. The volumes are updated every step by [VolumeUpdateSystem].
+
+use lib::atom::Position;
+use lib::integrator::{Step, Timestep};
+use lib::laser::gaussian::GaussianBeam;
+use lib::shapes::{Cylinder, Sphere};
+use lib::sim_region::{SimulationVolume, VolumeType};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+use crate::beams::BeamIndex;
+use crate::config::{positive, ConfigError};
+use crate::ramp::RampProfile;
+
+/// Shape of the simulation volume.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum VolumeConfig {
+    /// A sphere centred on the origin.
+    Sphere {
+        /// Radius at the start of the simulation, in m.
+        radius: f64,
+        /// Ramp of the radius; the radius is constant if omitted.
+        ramp: Option<RampProfile<f64>>,
+    },
+    /// A cylinder along each beam, centred on its focus, so atoms are kept anywhere in the beams
+    /// including their wings.
+    Beams {
+        /// Radius of each cylinder, in units of the current waist of its beam.
+        waists: f64,
+        /// Length of each cylinder, in m.
+        length: f64,
+    },
+}
+
+impl VolumeConfig {
+    pub fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        match self {
+            VolumeConfig::Sphere { radius, ramp } => {
+                positive(format!("{}.radius", key), *radius)?;
+                if let Some(ramp) = ramp {
+                    ramp.validate(&format!("{}.ramp", key), positive)?;
+                }
+            }
+            VolumeConfig::Beams { waists, length } => {
+                positive(format!("{}.waists", key), *waists)?;
+                positive(format!("{}.length", key), *length)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ramps the radius of a spherical simulation volume.
+pub struct RampedSphere {
+    /// Radius at t=0, in m.
+    pub initial: f64,
+    pub ramp: RampProfile<f64>,
+}
+
+impl Component for RampedSphere {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// Ties a cylindrical simulation volume to the beam with [BeamIndex] `beam`.
+pub struct BeamVolume {
+    pub beam: usize,
+    /// Radius of the cylinder, in units of the beam waist.
+    pub waists: f64,
+    /// In m.
+    pub length: f64,
+}
+
+impl Component for BeamVolume {
+    type Storage = HashMapStorage<Self>;
+}
+
+impl BeamVolume {
+    fn cylinder(&self, beam: &GaussianBeam) -> Cylinder {
+        let waist = beam.e_radius * 2.0_f64.sqrt();
+        Cylinder::new(self.waists * waist, self.length, beam.direction)
+    }
+}
+
+/// Creates the entities of the simulation volume. Must be called after the beams are created.
+pub fn create_volume(world: &mut World, config: &VolumeConfig) {
+    match config {
+        VolumeConfig::Sphere { radius, ramp } => {
+            let mut entity = world
+                .create_entity()
+                .with(Position { pos: Vector3::zeros() })
+                .with(Sphere { radius: *radius })
+                .with(SimulationVolume {
+                    volume_type: VolumeType::Inclusive,
+                });
+            if let Some(ramp) = ramp {
+                entity = entity.with(RampedSphere {
+                    initial: *radius,
+                    ramp: ramp.clone(),
+                });
+            }
+            entity.build();
+        }
+        VolumeConfig::Beams { waists, length } => {
+            let beams: Vec<(usize, GaussianBeam)> = (
+                &world.read_storage::<BeamIndex>(),
+                &world.read_storage::<GaussianBeam>(),
+            )
+                .join()
+                .map(|(index, beam)| (index.0, *beam))
+                .collect();
+            for (index, beam) in beams {
+                let volume = BeamVolume {
+                    beam: index,
+                    waists: *waists,
+                    length: *length,
+                };
+                world
+                    .create_entity()
+                    .with(Position {
+                        pos: beam.intersection,
+                    })
+                    .with(volume.cylinder(&beam))
+                    .with(volume)
+                    .with(SimulationVolume {
+                        volume_type: VolumeType::Inclusive,
+                    })
+                    .build();
+            }
+        }
+    }
+}
+
+/// Updates ramped spheres to the current time, and beam cylinders to the current beams.
+pub struct VolumeUpdateSystem;
+
+impl<'a> System<'a> for VolumeUpdateSystem {
+    type SystemData = (
+        WriteStorage<'a, Sphere>,
+        ReadStorage<'a, RampedSphere>,
+        WriteStorage<'a, Cylinder>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, BeamVolume>,
+        ReadStorage<'a, GaussianBeam>,
+        ReadStorage<'a, BeamIndex>,
+        ReadExpect<'a, Timestep>,
+        ReadExpect<'a, Step>,
+    );
+
+    fn run(
+        &mut self,
+        (mut spheres, ramped_spheres, mut cylinders, mut positions, beam_volumes, beams, indices, timestep, step): Self::SystemData,
+    ) {
+        let t = step.n as f64 * timestep.delta;
+        for (sphere, ramped) in (&mut spheres, &ramped_spheres).join() {
+            sphere.radius = ramped.ramp.value_at(ramped.initial, t);
+        }
+
+        let beams: Vec<(usize, GaussianBeam)> = (&indices, &beams).join().map(|(index, beam)| (index.0, *beam)).collect();
+        for (cylinder, position, volume) in (&mut cylinders, &mut positions, &beam_volumes).join() {
+            if let Some((_, beam)) = beams.iter().find(|(index, _)| *index == volume.beam) {
+                *cylinder = volume.cylinder(beam);
+                position.pos = beam.intersection;
+            }
+        }
+    }
+}